@@ -1,6 +1,6 @@
 use std::{fmt::Debug, iter::Peekable};
 
-use crate::tokenizer::{Token, Tokenizer};
+use crate::tokenizer::{SyntaxError, Token, Tokenizer};
 
 #[derive(Clone)]
 pub enum ExpressionAST {
@@ -146,6 +146,8 @@ pub enum ProgramAST {
 
 #[derive(Debug)]
 pub enum ParsingError {
+    Syntax(SyntaxError),
+    ExpressionTree(ExpressionTreeParsingError),
     UnexpectedNonFunctionToken(Token),
     UnexpectedExpressionTokenInLogicParsingPhase(Token),
     DidntParseWholeInput(ExpressionAST),
@@ -254,6 +256,16 @@ impl ProgramAST {
         }
     }
 
+    /// Runs the whole pipeline from source code to a finalized program
+    pub fn parse_source(source: &str) -> Result<Self, ParsingError> {
+        let mut tokenizer = Tokenizer::new(source);
+        tokenizer.verify_syntax().map_err(ParsingError::Syntax)?;
+        let ast = ExpressionAST::parse(tokenizer).map_err(ParsingError::ExpressionTree)?;
+        let mut ast = Self::parse(ast)?;
+        ast.finalize();
+        Ok(ast)
+    }
+
     pub fn finalize(&mut self) {
         match self {
             ProgramAST::FunctionCall { function, arg } => {
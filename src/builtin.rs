@@ -1,163 +1,251 @@
-use std::process::Command;
-
 use crate::{
     ast_parser::ProgramAST,
     interpreter::{
         self, InterpreterContext, InterpreterFunctionDef, RuntimeError, Value, ValueFunction,
     },
+    machine::Signal,
+    native::partial,
+    permissions::Permission,
+    prelude::load_prelude,
 };
 
+mod control;
+mod coroutine;
+mod fs;
+mod gc;
+pub mod heapdump;
+mod iter;
+mod list;
+mod map;
+mod memo;
+pub(crate) mod module;
+mod process;
+mod record;
+mod string;
+mod vector;
+
+#[derive(Debug)]
+enum AllocationData {
+    Cells(Vec<usize>),
+    /// One byte per address, used for text
+    Bytes(Vec<u8>),
+    /// Takes up a single address, the entries are only accessible through the map builtins
+    Map(map::Map),
+}
+
+const MAP_ACCESS: RuntimeError =
+    RuntimeError::ExplicitlyRaisedMessage("maps are only accessible through the map builtins");
+
+impl AllocationData {
+    fn len(&self) -> usize {
+        match self {
+            AllocationData::Cells(cells) => cells.len(),
+            AllocationData::Bytes(bytes) => bytes.len(),
+            AllocationData::Map(_) => 1,
+        }
+    }
+
+    pub fn get(&self, offset: usize) -> Result<usize, RuntimeError> {
+        match self {
+            AllocationData::Cells(cells) => Ok(cells[offset]),
+            AllocationData::Bytes(bytes) => Ok(bytes[offset] as usize),
+            AllocationData::Map(_) => Err(MAP_ACCESS),
+        }
+    }
+
+    pub fn set(&mut self, offset: usize, value: usize) -> Result<(), RuntimeError> {
+        match self {
+            AllocationData::Cells(cells) => cells[offset] = value,
+            AllocationData::Bytes(bytes) => {
+                bytes[offset] = u8::try_from(value).map_err(|_| {
+                    RuntimeError::ExplicitlyRaisedMessage("value does not fit into a byte")
+                })?
+            }
+            AllocationData::Map(_) => return Err(MAP_ACCESS),
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 struct Allocation {
     start_addr: usize,
-    data: Vec<usize>,
+    data: AllocationData,
+    /// Addresses kept free for the allocation to grow into, see `vpush`
+    reserved: usize,
 }
 
-#[derive(Debug)]
-struct BuiltinState {
+impl Allocation {
+    /// The first address after the allocation and its reserved space
+    fn end(&self) -> usize {
+        self.start_addr + self.data.len().max(self.reserved)
+    }
+}
+
+/// The heap used by the builtins
+#[derive(Debug, Default)]
+pub struct BuiltinState {
     heap: Vec<Allocation>,
+    gc_stats: gc::GcStats,
+    strict: bool,
 }
 
+/// In strict mode every allocation starts at its own slot and the lower half of
+/// a pointer is the offset into it, so the base allocation of a pointer can be
+/// recovered even after pointer arithmetic.
+const STRICT_OFFSET_BITS: u32 = usize::BITS / 2;
+const STRICT_OFFSET_MASK: usize = (1 << STRICT_OFFSET_BITS) - 1;
+
 impl BuiltinState {
-    fn alloc(&mut self, size: usize) -> usize {
-        match self.heap.last() {
-            Some(s) => {
-                let start_addr = s.start_addr + s.data.len();
-                self.heap.push(Allocation {
-                    start_addr,
-                    data: vec![0; size],
-                });
-                start_addr
-            }
-            None => {
-                self.heap.push(Allocation {
-                    start_addr: 0x1024,
-                    data: vec![0; size],
-                });
-                0x1024
+    pub fn alloc(&mut self, size: usize) -> Result<usize, RuntimeError> {
+        self.check_slot_size(size)?;
+        self.push_allocation(AllocationData::Cells(vec![0; size]))
+    }
+
+    pub fn alloc_bytes(&mut self, bytes: Vec<u8>) -> Result<usize, RuntimeError> {
+        self.push_allocation(AllocationData::Bytes(bytes))
+    }
+
+    pub fn alloc_str(&mut self, string: &str) -> Result<usize, RuntimeError> {
+        self.alloc_bytes(string.as_bytes().to_vec())
+    }
+
+    /// Fails in strict mode if the data doesn't fit into a slot
+    fn push_allocation(&mut self, data: AllocationData) -> Result<usize, RuntimeError> {
+        self.check_slot_size(data.len())?;
+        let start_addr = match self.heap.last() {
+            Some(s) if self.strict => {
+                ((s.start_addr >> STRICT_OFFSET_BITS) + 1) << STRICT_OFFSET_BITS
             }
+            Some(s) => s.end(),
+            None if self.strict => 1 << STRICT_OFFSET_BITS,
+            None => 0x1024,
+        };
+        self.heap.push(Allocation {
+            start_addr,
+            data,
+            reserved: 0,
+        });
+        Ok(start_addr)
+    }
+
+    /// Fails in strict mode if `len` addresses would spill into the next slot
+    fn check_slot_size(&self, len: usize) -> Result<(), RuntimeError> {
+        if self.strict && len > STRICT_OFFSET_MASK {
+            return Err(RuntimeError::ExplicitlyRaisedMessage(
+                "allocation too large for strict memory",
+            ));
         }
+        Ok(())
     }
 
-    fn get(&self, addr: usize) -> Option<usize> {
-        for allocation in &self.heap {
-            if addr >= allocation.start_addr && addr < allocation.start_addr + allocation.data.len()
-            {
-                return Some(allocation.data[addr - allocation.start_addr]);
+    fn allocation(&self, addr: usize) -> Option<&Allocation> {
+        self.heap.iter().find(|a| a.start_addr == addr)
+    }
+
+    /// Reads the string starting at `addr`. Byte buffers are decoded as UTF-8,
+    /// cell allocations as one unicode code point per cell.
+    pub fn read_str(&self, addr: usize) -> Result<String, RuntimeError> {
+        match self.allocation(addr).map(|a| &a.data) {
+            Some(AllocationData::Bytes(bytes)) => Ok(String::from_utf8_lossy(bytes).into_owned()),
+            Some(AllocationData::Cells(cells)) => {
+                Ok(cells.iter().map(|&c| cell_to_char(c)).collect())
             }
+            Some(AllocationData::Map(_)) => Err(RuntimeError::ExplicitlyRaisedMessage(
+                "a map is not a string",
+            )),
+            None => Err(RuntimeError::ExplicitlyRaisedMessage(
+                "allocation not found",
+            )),
         }
-        None
     }
 
-    fn set(&mut self, addr: usize, value: usize) -> bool {
-        for allocation in &mut self.heap {
+    /// Resolves `addr` to an allocation index and the offset into it.
+    fn locate(&self, addr: usize) -> Result<(usize, usize), RuntimeError> {
+        if self.strict {
+            let base = addr & !STRICT_OFFSET_MASK;
+            let offset = addr & STRICT_OFFSET_MASK;
+            if let Ok(index) = self.heap.binary_search_by_key(&base, |a| a.start_addr) {
+                let len = self.heap[index].data.len();
+                if offset >= len {
+                    return Err(RuntimeError::OutOfBounds { base, offset, len });
+                }
+                return Ok((index, offset));
+            }
+        }
+        // allocations made before strict mode was enabled are looked up by range
+        for (index, allocation) in self.heap.iter().enumerate() {
             if addr >= allocation.start_addr && addr < allocation.start_addr + allocation.data.len()
             {
-                allocation.data[addr - allocation.start_addr] = value;
-                return true;
+                return Ok((index, addr - allocation.start_addr));
             }
         }
-        false
+        Err(RuntimeError::ExplicitlyRaisedMessage(
+            "address not previously allocd",
+        ))
     }
+
+    pub fn get(&self, addr: usize) -> Result<usize, RuntimeError> {
+        let (index, offset) = self.locate(addr)?;
+        self.heap[index].data.get(offset)
+    }
+
+    pub fn set(&mut self, addr: usize, value: usize) -> Result<(), RuntimeError> {
+        let (index, offset) = self.locate(addr)?;
+        self.heap[index].data.set(offset, value)
+    }
+}
+
+fn cell_to_char(cell: usize) -> char {
+    u32::try_from(cell)
+        .ok()
+        .and_then(char::from_u32)
+        .unwrap_or(char::REPLACEMENT_CHARACTER)
 }
 
+/// Enables or disables strict memory for allocations made from now on. Pointers
+/// into strict allocations carry their base allocation, and reads or writes past
+/// its end fail with `RuntimeError::OutOfBounds` instead of touching whatever
+/// allocation comes next.
+pub fn set_strict_memory(interpreter: &mut InterpreterContext, strict: bool) {
+    interpreter.state_or_default::<BuiltinState>().strict = strict;
+}
+
+/// Creates an interpreter with all builtins and the prelude loaded
 pub fn create_builtin_interpreter() -> InterpreterContext {
+    let mut interpreter = create_builtin_interpreter_without_prelude();
+    load_prelude(&mut interpreter).expect("the prelude evaluates");
+    interpreter
+}
+
+pub fn create_builtin_interpreter_without_prelude() -> InterpreterContext {
     let mut interpreter = InterpreterContext::new();
-    interpreter.state = Box::from(BuiltinState { heap: vec![] });
+    interpreter.insert_state(BuiltinState::default());
     register_builtins(&mut interpreter);
     interpreter
 }
 
 pub fn register_builtins(interpreter: &mut InterpreterContext) {
-    interpreter.register_builtin("alloc".to_owned(), 1, |interpreter, args| match interpreter
-        .state
-        .downcast_mut::<BuiltinState>(
-    ) {
-        Some(state) => match &args[0] {
-            Value::Number(n) => Ok(Value::Number(state.alloc(*n))),
-            Value::Function(_) => Err(RuntimeError::ExplicitlyRaisedMessage(
-                "alloc param should be number",
-            )),
-        },
-        None => Err(RuntimeError::ExplicitlyRaisedMessage(
-            "Unexpected interpreter state",
-        )),
-    });
-    interpreter.register_builtin("len".to_owned(), 1, |interpreter, args| {
-        let addr = match args[0] {
-            Value::Number(n) => n,
-            Value::Function(_) => {
-                return Err(RuntimeError::ExplicitlyRaisedMessage(
-                    "len needs addr as parameter",
-                ))
-            }
-        };
-        match interpreter.state.downcast_mut::<BuiltinState>() {
-            Some(state) => match state.heap.iter().find(|e| e.start_addr == addr) {
-                Some(a) => Ok(Value::Number(a.data.len())),
-                None => Err(RuntimeError::ExplicitlyRaisedMessage(
-                    "allocation for addr not found",
-                )),
-            },
-            None => Err(RuntimeError::ExplicitlyRaisedMessage(
-                "interpreter in invalid state",
-            )),
-        }
-    });
-    interpreter.register_builtin("*".to_owned(), 1, |interpreter, args| {
-        let addr = match args[0] {
-            Value::Number(n) => n,
-            Value::Function(_) => {
-                return Err(RuntimeError::ExplicitlyRaisedMessage(
-                    "* needs addr as parameter",
-                ))
-            }
-        };
-        match interpreter.state.downcast_mut::<BuiltinState>() {
-            Some(state) => match state.get(addr) {
-                Some(value) => Ok(Value::Number(value as usize)),
-                None => Err(RuntimeError::ExplicitlyRaisedMessage(
-                    "Deref addr not found",
-                )),
-            },
-            None => Err(RuntimeError::ExplicitlyRaisedMessage(
-                "interpreter in invalid state",
-            )),
-        }
-    });
-    interpreter.register_builtin("=".to_owned(), 2, |interpreter, args| {
-        let addr = match args[0] {
-            Value::Number(n) => n,
-            Value::Function(_) => {
-                return Err(RuntimeError::ExplicitlyRaisedMessage(
-                    "len needs addr as parameter",
-                ))
-            }
-        };
-        let value = match args[1] {
-            Value::Number(n) => n,
-            Value::Function(_) => {
-                return Err(RuntimeError::ExplicitlyRaisedMessage(
-                    "len needs addr as parameter",
-                ))
-            }
-        };
-        match interpreter.state.downcast_mut::<BuiltinState>() {
-            Some(state) => match state.set(addr, value) {
-                true => Ok(Value::Number(1)),
-                false => Err(RuntimeError::ExplicitlyRaisedMessage(
-                    "address not previously allocd",
-                )),
-            },
-            None => Err(RuntimeError::ExplicitlyRaisedMessage(
-                "interpreter in invalid state",
-            )),
-        }
-    });
-    interpreter.register_builtin("number?".to_owned(), 1, |_, args| match args[0] {
-        Value::Number(_) => Ok(Value::Number(1)),
-        Value::Function(_) => Ok(Value::Number(0)),
-    });
+    control::register(interpreter);
+    coroutine::register(interpreter);
+    fs::register(interpreter);
+    gc::register(interpreter);
+    heapdump::register(interpreter);
+    iter::register(interpreter);
+    list::register(interpreter);
+    map::register(interpreter);
+    memo::register(interpreter);
+    module::register(interpreter);
+    process::register(interpreter);
+    record::register(interpreter);
+    string::register(interpreter);
+    vector::register(interpreter);
+    interpreter.register_native("alloc", alloc);
+    interpreter.register_native("len", len);
+    interpreter.register_native("*", deref);
+    interpreter.register_native("=", assign);
+    interpreter.register_native_fn("number?", is_number);
+    interpreter.register_native_fn("func?", is_function);
     interpreter.register_builtin("bind".to_owned(), 2, |interpreter, args| {
         if let (Value::Function(func), Value::Function(to_return)) = (&args[0], &args[1]) {
             if let InterpreterFunctionDef::FunctionDef { name: _, def } = &func.func {
@@ -188,78 +276,20 @@ pub fn register_builtins(interpreter: &mut InterpreterContext) {
             "Wrong parameter to bind",
         ))
     });
-    interpreter.register_builtin(
-        "printstr".to_owned(),
-        1,
-        |interpreter, args| match interpreter.state.downcast_mut::<BuiltinState>() {
-            Some(state) => match args[0] {
-                Value::Number(n) => match state.heap.iter().find(|a| a.start_addr == n) {
-                    Some(v) => {
-                        println!(
-                            "{}",
-                            std::str::from_utf8(
-                                &v.data.iter().map(|&e| e as u8).collect::<Vec<u8>>()
-                            )
-                            .unwrap()
-                        );
-                        Ok(Value::Number(n))
-                    }
-                    None => Err(RuntimeError::ExplicitlyRaisedMessage(
-                        "allocation not found",
-                    )),
-                },
-                Value::Function(_) => Err(RuntimeError::ExplicitlyRaisedMessage(
-                    "need ptr to print string",
-                )),
-            },
-            None => Err(RuntimeError::ExplicitlyRaisedMessage(
-                "invalid interpreter state",
-            )),
-        },
-    );
-    interpreter.register_builtin("+".to_owned(), 2, |_, args| {
-        if let Value::Number(num) = args[0] {
-            if let Value::Number(num2) = args[1] {
-                return Ok(Value::Number(num + num2));
-            }
-        }
-        Err(interpreter::RuntimeError::ExplicitlyRaised)
-    });
-    interpreter.register_builtin("mul".to_owned(), 2, |_, args| {
-        if let Value::Number(num) = args[0] {
-            if let Value::Number(num2) = args[1] {
-                return Ok(Value::Number(num * num2));
-            }
-        }
-        Err(interpreter::RuntimeError::ExplicitlyRaised)
-    });
-
-    interpreter.register_builtin("-".to_owned(), 2, |_, args| {
-        if let Value::Number(num) = args[0] {
-            if let Value::Number(num2) = args[1] {
-                if num2 > num {
-                    return Ok(Value::Number(usize::MAX - (num2 - num)));
-                }
-                return Ok(Value::Number(num - num2));
-            }
-        }
-        Ok(Value::Number(0))
-    });
-    interpreter.register_builtin("not".to_owned(), 1, |_, args| {
-        if let Value::Number(num) = args[0] {
-            return Ok(Value::Number(if num == 0 { 1 } else { 0 }));
-        }
-        Err(interpreter::RuntimeError::ExplicitlyRaised)
-    });
-    interpreter.register_builtin("print".to_owned(), 1, |_, args| {
-        println!("{:?}", args[0]);
-        Ok(args[0].clone())
-    });
+    interpreter.register_native_fn("printstr", printstr);
+    interpreter.register_native_fn("+", add);
+    interpreter.register_native_fn("mul", mul);
+    interpreter.register_native_fn("-", sub);
+    interpreter.register_native_fn("not", not);
+    interpreter.register_native_fn("<", less);
+    interpreter.register_native_fn(">", greater);
+    interpreter.register_native_fn("print", print);
     interpreter.register_builtin("panic".to_owned(), 1, |_, _| {
         Err(interpreter::RuntimeError::ExplicitlyRaised)
     });
     interpreter.register_builtin("let".to_owned(), 2, |interpreter, args| match &args[0] {
         Value::Number(n) => Err(interpreter::RuntimeError::ValueNotAFunction(*n)),
+        Value::Record(record) => Err(RuntimeError::RecordNotAFunction(record.clone())),
         Value::Function(fname) => match &fname.func {
             interpreter::InterpreterFunctionDef::BuiltIn {
                 name: _,
@@ -284,80 +314,11 @@ pub fn register_builtins(interpreter: &mut InterpreterContext) {
             }
         },
     });
-    interpreter.register_builtin("if".to_owned(), 3, |interpreter, args| {
-        let expression = args.get(0).unwrap();
-        let body = args.get(1).unwrap();
-        let elseval = args.get(2).unwrap();
-        if let (Value::Function(expr), Value::Function(body), Value::Function(elseval)) =
-            (expression, body, elseval)
-        {
-            if let Value::Number(n) = interpreter.run_func_value(expr.clone(), vec![])? {
-                if n != 0 {
-                    return interpreter.run_func_value(body.clone(), vec![]);
-                } else {
-                    return interpreter.run_func_value(elseval.clone(), vec![]);
-                }
-            }
-        }
-        if let (Value::Number(expr), Value::Function(body), Value::Function(elseval)) =
-            (expression, body, elseval)
-        {
-            if *expr != 0 {
-                return interpreter.run_func_value(body.clone(), vec![]);
-            } else {
-                return interpreter.run_func_value(elseval.clone(), vec![]);
-            }
-        }
-        Ok(Value::Number(0))
-    });
-    interpreter.register_builtin("read".to_owned(), 0, |interpreter, _| {
-        let stdin = std::io::stdin();
-        let read = std::io::BufRead::lines(stdin.lock())
-            .next()
-            .unwrap()
-            .unwrap();
-        match interpreter.state.downcast_mut::<BuiltinState>() {
-            Some(state) => {
-                let addr = state.alloc(read.len());
-                for (i, c) in read.chars().enumerate() {
-                    state.set(addr + i, c as usize);
-                }
-                Ok(Value::Number(addr))
-            }
-            None => Err(RuntimeError::ExplicitlyRaisedMessage(
-                "interpreter in invalid state",
-            )),
-        }
-    });
-    interpreter.register_builtin("exec".to_owned(), 1, |interpreter, args| {
-        if let Value::Number(strptr) = args[0] {
-            match interpreter.state.downcast_mut::<BuiltinState>() {
-                Some(state) => match state.heap.iter().find(|a| a.start_addr == strptr) {
-                    Some(alloc) => {
-                        let command =
-                            String::from_utf8(alloc.data.iter().map(|e| *e as u8).collect())
-                                .unwrap();
-                        let return_str = match Command::new("sh").arg("-c").arg(command).output() {
-                            Ok(ok) => String::from_utf8(ok.stdout).unwrap(),
-                            Err(e) => e.to_string(),
-                        };
-                        let addr = state.alloc(return_str.len());
-                        for (i, e) in return_str.chars().enumerate() {
-                            state.set(addr + i, e as usize);
-                        }
-                        return Ok(Value::Number(addr));
-                    }
-                    _ => {}
-                },
-                None => {
-                    return Err(RuntimeError::ExplicitlyRaisedMessage(
-                        "invalid interpreter state",
-                    ))
-                }
-            }
-        }
-        Err(RuntimeError::ExplicitlyRaisedMessage("Invalid str ptr"))
-    });
+    interpreter.register_native_fn("if", if_else);
+    interpreter.register_native_fn("while", while_loop);
+    interpreter.register_native_fn("for", for_loop);
+    interpreter.register_native_fn("repeat", repeat);
+    interpreter.register_native_fn("read", read);
     interpreter.register_builtin("inspect".to_owned(), 1, |interpreter, args| {
         if let Value::Function(ValueFunction {
             func: InterpreterFunctionDef::FunctionDef { name: _, def },
@@ -373,27 +334,263 @@ pub fn register_builtins(interpreter: &mut InterpreterContext) {
                     _ => {}
                 }
             }
-            match interpreter.state.downcast_mut::<BuiltinState>() {
-                Some(state) => {
-                    let addr = state.alloc(list.len());
-                    for (i, e) in list.into_iter().enumerate() {
-                        let addr_str = state.alloc(e.len());
-                        state.set(addr + i, addr_str);
-                        for (i, e) in e.chars().enumerate() {
-                            state.set(addr_str + i, e as usize);
-                        }
-                    }
-                    return Ok(Value::Number(addr));
-                }
-                None => {
-                    return Err(RuntimeError::ExplicitlyRaisedMessage(
-                        "invalid interpreter state",
-                    ))
-                }
+            let state = interpreter.state_or_default::<BuiltinState>();
+            let addr = state.alloc(list.len())?;
+            for (i, e) in list.into_iter().enumerate() {
+                let addr_str = state.alloc_str(&e)?;
+                state.set(addr + i, addr_str)?;
             }
+            return Ok(Value::Number(addr));
         }
         Err(RuntimeError::ExplicitlyRaisedMessage(
             "Parameter needs to be a function of values",
         ))
     });
+    interpreter.register_native("balloc", balloc);
+    interpreter.register_native("encode", encode);
+    interpreter.register_native("decode", decode);
+}
+
+fn alloc(state: &mut BuiltinState, size: usize) -> Result<usize, RuntimeError> {
+    if state.strict && size > STRICT_OFFSET_MASK {
+        return Err(RuntimeError::ExplicitlyRaisedMessage(
+            "allocation too large for strict memory",
+        ));
+    }
+    state.alloc(size)
+}
+
+fn balloc(state: &mut BuiltinState, size: usize) -> Result<usize, RuntimeError> {
+    state.check_slot_size(size)?;
+    state.alloc_bytes(vec![0; size])
+}
+
+fn len(state: &mut BuiltinState, addr: usize) -> Result<usize, RuntimeError> {
+    match state.allocation(addr) {
+        Some(a) => Ok(a.data.len()),
+        None => Err(RuntimeError::ExplicitlyRaisedMessage(
+            "allocation for addr not found",
+        )),
+    }
+}
+
+fn deref(state: &mut BuiltinState, addr: usize) -> Result<usize, RuntimeError> {
+    state.get(addr)
+}
+
+fn assign(state: &mut BuiltinState, addr: usize, value: usize) -> Result<usize, RuntimeError> {
+    state.set(addr, value)?;
+    Ok(1)
+}
+
+fn is_number(_: &mut InterpreterContext, value: Value) -> Result<bool, RuntimeError> {
+    Ok(matches!(value, Value::Number(_)))
+}
+
+fn is_function(_: &mut InterpreterContext, value: Value) -> Result<bool, RuntimeError> {
+    Ok(matches!(value, Value::Function(_)))
+}
+
+fn add(_: &mut InterpreterContext, num: usize, num2: usize) -> Result<usize, RuntimeError> {
+    Ok(num.wrapping_add(num2))
+}
+
+fn mul(_: &mut InterpreterContext, num: usize, num2: usize) -> Result<usize, RuntimeError> {
+    Ok(num.wrapping_mul(num2))
+}
+
+fn sub(_: &mut InterpreterContext, num: usize, num2: usize) -> Result<usize, RuntimeError> {
+    if num2 > num {
+        return Ok(usize::MAX - (num2 - num));
+    }
+    Ok(num - num2)
+}
+
+fn not(_: &mut InterpreterContext, num: usize) -> Result<bool, RuntimeError> {
+    Ok(num == 0)
+}
+
+/// Evaluates the branch chosen by `condition`, a number or a function returning
+/// one. Both the condition and the branch are run by the evaluator, so they can yield.
+fn if_else(
+    interpreter: &mut InterpreterContext,
+    condition: Value,
+    body: Value,
+    elseval: Value,
+) -> Result<Value, RuntimeError> {
+    match condition {
+        Value::Function(condition) => Err(Signal::Call {
+            func: condition,
+            args: vec![],
+            then: Some(partial("if", branch, vec![body, elseval])),
+        }
+        .into()),
+        condition => branch(interpreter, body, elseval, condition),
+    }
+}
+
+fn branch(
+    _: &mut InterpreterContext,
+    body: Value,
+    elseval: Value,
+    condition: Value,
+) -> Result<Value, RuntimeError> {
+    match (condition, body, elseval) {
+        (Value::Number(n), Value::Function(body), Value::Function(elseval)) => Err(Signal::Call {
+            func: if n != 0 { body } else { elseval },
+            args: vec![],
+            then: None,
+        }
+        .into()),
+        _ => Ok(Value::Number(0)),
+    }
+}
+
+/// Calls `body` as long as `cond` returns non-zero, both with the argument 0.
+/// The evaluator runs the loop, so it doesn't grow the Rust stack and `break`,
+/// `continue` and `yield` work in the body.
+fn while_loop(
+    _: &mut InterpreterContext,
+    cond: ValueFunction,
+    body: ValueFunction,
+) -> Result<Value, RuntimeError> {
+    Err(Signal::While { cond, body }.into())
+}
+
+/// Calls `body` with every number from `from` up to (excluding) `to`
+fn for_loop(
+    _: &mut InterpreterContext,
+    from: usize,
+    to: usize,
+    body: ValueFunction,
+) -> Result<Value, RuntimeError> {
+    Err(Signal::For { from, to, body }.into())
+}
+
+/// Calls `body` `n` times, with the number of the iteration
+fn repeat(
+    interpreter: &mut InterpreterContext,
+    n: usize,
+    body: ValueFunction,
+) -> Result<Value, RuntimeError> {
+    for_loop(interpreter, 0, n, body)
+}
+
+fn less(_: &mut InterpreterContext, num: usize, num2: usize) -> Result<bool, RuntimeError> {
+    Ok(num < num2)
+}
+
+fn greater(_: &mut InterpreterContext, num: usize, num2: usize) -> Result<bool, RuntimeError> {
+    Ok(num > num2)
+}
+
+fn print(interpreter: &mut InterpreterContext, value: Value) -> Result<Value, RuntimeError> {
+    interpreter.println(format!("{:?}", value))?;
+    Ok(value)
+}
+
+fn printstr(interpreter: &mut InterpreterContext, strptr: usize) -> Result<usize, RuntimeError> {
+    let string = interpreter
+        .state_or_default::<BuiltinState>()
+        .read_str(strptr)?;
+    interpreter.println(string)?;
+    Ok(strptr)
+}
+
+/// Reads a line from stdin, returning 0 at the end of input
+fn read(interpreter: &mut InterpreterContext) -> Result<usize, RuntimeError> {
+    interpreter.check_permission(Permission::Stdin)?;
+    match interpreter.io().read_line().map_err(RuntimeError::Io)? {
+        Some(line) => interpreter
+            .state_or_default::<BuiltinState>()
+            .alloc_str(&line),
+        None => Ok(0),
+    }
+}
+
+fn encode(state: &mut BuiltinState, strptr: usize) -> Result<usize, RuntimeError> {
+    let string = state.read_str(strptr)?;
+    state.alloc_str(&string)
+}
+
+fn decode(state: &mut BuiltinState, strptr: usize) -> Result<usize, RuntimeError> {
+    let chars = state
+        .read_str(strptr)?
+        .chars()
+        .map(|c| c as usize)
+        .collect();
+    state.push_allocation(AllocationData::Cells(chars))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{balloc, BuiltinState, STRICT_OFFSET_MASK};
+    use crate::{
+        engine::Engine,
+        interpreter::{RuntimeError, Value},
+        io::MemoryIo,
+    };
+
+    #[test]
+    fn strict_memory_reports_out_of_bounds() {
+        let mut state = BuiltinState {
+            strict: true,
+            ..Default::default()
+        };
+        let vec = state.alloc(3).unwrap();
+        let next = state.alloc(3).unwrap();
+        state.set(next, 42).unwrap();
+
+        assert_eq!(state.get(vec + 2).unwrap(), 0);
+        match state.get(vec + 3) {
+            Err(RuntimeError::OutOfBounds { base, offset, len }) => {
+                assert_eq!((base, offset, len), (vec, 3, 3))
+            }
+            other => panic!("expected out of bounds, got {:?}", other),
+        }
+        // nothing may spill into the next slot
+        assert!(state.alloc(STRICT_OFFSET_MASK + 1).is_err());
+        assert!(balloc(&mut state, STRICT_OFFSET_MASK + 1).is_err());
+    }
+
+    #[test]
+    fn loops_do_not_grow_the_stack() {
+        let mut engine = Engine::new();
+        engine.eval("let { total; } (alloc 1)").unwrap();
+        engine
+            .eval("for 0 20000 { i => total `= (+ (* total) i); }")
+            .unwrap();
+        assert!(matches!(
+            engine.eval("* total"),
+            Ok(Value::Number(199990000))
+        ));
+        let allocations = engine.interpreter().heap_dump().unwrap().len();
+        engine
+            .eval("{ total `= 0; repeat 5 { total `= (+ (* total) 2); }; }")
+            .unwrap();
+        assert!(matches!(engine.eval("* total"), Ok(Value::Number(10))));
+        // the loop counter isn't on the heap
+        assert_eq!(engine.interpreter().heap_dump().unwrap().len(), allocations);
+        assert!(matches!(
+            engine.eval("{ while { < (* total) 20000; } { total `= (+ (* total) 1); }; * total; }"),
+            Ok(Value::Number(20000))
+        ));
+    }
+
+    #[test]
+    fn read_keeps_non_ascii_text() {
+        let mut engine = Engine::new();
+        let io = MemoryIo::new();
+        io.push_input("héllo\n");
+        engine.interpreter_mut().set_io(io.clone());
+        engine.interpreter_mut().permissions_mut().stdin = true;
+        engine.eval("let { s; } (read 0)").unwrap();
+        assert!(matches!(engine.eval("len s"), Ok(Value::Number(6))));
+        assert!(matches!(
+            engine.eval("len (decode s)"),
+            Ok(Value::Number(5))
+        ));
+        engine.eval("printstr (encode (decode s))").unwrap();
+        assert_eq!(io.take_stdout(), "héllo\n");
+    }
 }
@@ -0,0 +1,130 @@
+use crate::{
+    interpreter::{InterpreterContext, RuntimeError, Value, ValueFunction},
+    machine::Signal,
+};
+
+pub fn register(interpreter: &mut InterpreterContext) {
+    interpreter.register_native_fn("callcc", callcc);
+    interpreter.register_native_fn("return", return_value);
+    interpreter.register_native_fn("break", break_loop);
+    interpreter.register_native_fn("continue", continue_loop);
+}
+
+/// Leaves the innermost function with parameters. Blocks without parameters,
+/// like the branches of `if`, and loop bodies are left as well.
+fn return_value(_: &mut InterpreterContext, value: Value) -> Result<Value, RuntimeError> {
+    Err(Signal::Return(value).into())
+}
+
+/// Leaves the innermost `while`, `for`, `repeat` or `foreach` loop, which returns 0
+fn break_loop(_: &mut InterpreterContext) -> Result<Value, RuntimeError> {
+    Err(Signal::Break.into())
+}
+
+/// Continues with the next iteration of the innermost loop
+fn continue_loop(_: &mut InterpreterContext) -> Result<Value, RuntimeError> {
+    Err(Signal::Continue.into())
+}
+
+/// Calls `func` with the current continuation, a function that makes `callcc`
+/// return its argument when called, even after `callcc` has returned already
+fn callcc(_: &mut InterpreterContext, func: ValueFunction) -> Result<Value, RuntimeError> {
+    Err(Signal::CallCC(func).into())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        engine::{Engine, Error},
+        interpreter::{RuntimeError, Value},
+    };
+
+    #[test]
+    fn continuations_escape_and_reenter() {
+        let mut engine = Engine::new();
+        // escaping from a callback of a builtin
+        assert!(matches!(
+            engine.eval(
+                "callcc { k => foreach ([ 1 2 3 4 ]) { x => if (eq x 3) { k (mul x 10); } { 0; }; }; 0; }"
+            ),
+            Ok(Value::Number(30))
+        ));
+        // re-entering a continuation after `callcc` returned
+        assert!(matches!(
+            engine.eval(
+                "{
+                    let { runs; } (alloc 1);
+                    let { k; } (callcc { c => c; });
+                    runs `= (+ (* runs) 1);
+                    if (func? k) { k 0; } { 0; };
+                    * runs;
+                }"
+            ),
+            Ok(Value::Number(2))
+        ));
+        // the continuation of a statement stays valid across evaluations
+        engine.eval("let { later; } (callcc { c => c; })").unwrap();
+        assert!(matches!(engine.eval("func? later"), Ok(Value::Number(1))));
+        assert!(matches!(engine.eval("+ 1 (later 5)"), Ok(Value::Number(0))));
+        assert!(matches!(engine.eval("later"), Ok(Value::Number(5))));
+    }
+
+    #[test]
+    fn control_flow_signals() {
+        let mut engine = Engine::new();
+        engine
+            .eval(
+                "let { first_over; } { xs limit =>
+                    foreach xs { x => if (> x limit) { return x; } { 0; }; };
+                    0;
+                }",
+            )
+            .unwrap();
+        // `return` in a loop body leaves the function around the loop
+        assert!(matches!(
+            engine.eval("first_over ([ 1 5 9 ]) 4"),
+            Ok(Value::Number(5))
+        ));
+        engine
+            .eval(
+                "let { f; } { n =>
+                    for 0 10 { i => if (eq i 3) { return 99; } { 0; }; };
+                    7;
+                }",
+            )
+            .unwrap();
+        assert!(matches!(engine.eval("f 0"), Ok(Value::Number(99))));
+        engine
+            .eval(
+                "let { sum_below; } { n =>
+                    let { i; } (alloc 1);
+                    let { total; } (alloc 1);
+                    while { 1; } {
+                        i `= (+ (* i) 1);
+                        if (eq (* i) n) { return (* total); } { 0; };
+                        if (eq (* i) 3) { continue 0; } { 0; };
+                        total `= (+ (* total) (* i));
+                    };
+                }",
+            )
+            .unwrap();
+        assert!(matches!(engine.eval("sum_below 6"), Ok(Value::Number(12))));
+        assert!(matches!(
+            engine.eval("while { 1; } { break 0; }"),
+            Ok(Value::Number(0))
+        ));
+        // signals don't reach the host
+        assert!(matches!(
+            engine.eval("break 0"),
+            Err(Error::Runtime(RuntimeError::ExplicitlyRaisedMessage(
+                "break or continue outside of a loop"
+            )))
+        ));
+        assert!(matches!(
+            engine.eval("{ continue 0; }"),
+            Err(Error::Runtime(RuntimeError::ExplicitlyRaisedMessage(
+                "break or continue outside of a loop"
+            )))
+        ));
+    }
+}
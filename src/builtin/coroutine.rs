@@ -0,0 +1,135 @@
+use crate::{
+    interpreter::{InterpreterContext, InterpreterFunctionDef, RuntimeError, Value, ValueFunction},
+    machine::{Coroutines, Signal},
+    native::partial,
+};
+
+use super::iter::done;
+
+pub fn register(interpreter: &mut InterpreterContext) {
+    interpreter.register_native_fn("coroutine", coroutine);
+    interpreter.register_native_fn("yield", yield_value);
+    interpreter.register_native_fn("resume", resume);
+}
+
+/// Wraps `func` into a coroutine, which is called with the value of the first
+/// `resume`. Calling a coroutine value resumes it as well, so coroutines are
+/// also iterators.
+fn coroutine(
+    interpreter: &mut InterpreterContext,
+    func: ValueFunction,
+) -> Result<ValueFunction, RuntimeError> {
+    let body = partial("coroutine body", body, vec![Value::Function(func)]);
+    let id = interpreter.state_or_default::<Coroutines>().spawn(body);
+    Ok(partial("coroutine", resume_id, vec![Value::Number(id)]))
+}
+
+/// Runs the wrapped function, returning `done` instead of its result once it finishes
+fn body(
+    _: &mut InterpreterContext,
+    func: ValueFunction,
+    value: Value,
+) -> Result<Value, RuntimeError> {
+    Err(Signal::Call {
+        func,
+        args: vec![value],
+        then: Some(partial("coroutine end", end, vec![])),
+    }
+    .into())
+}
+
+fn end(_: &mut InterpreterContext, _: Value) -> Result<Value, RuntimeError> {
+    Ok(done())
+}
+
+fn resume_id(
+    interpreter: &mut InterpreterContext,
+    id: usize,
+    value: Value,
+) -> Result<Value, RuntimeError> {
+    if interpreter.state_or_default::<Coroutines>().is_finished(id) {
+        return Ok(done());
+    }
+    Err(Signal::Resume(id, value).into())
+}
+
+fn yield_value(_: &mut InterpreterContext, value: Value) -> Result<Value, RuntimeError> {
+    Err(Signal::Yield(value).into())
+}
+
+/// Continues `co` until it yields, passing `value` as the result of `yield`.
+/// Returns `done` once the coroutine has finished.
+fn resume(
+    _: &mut InterpreterContext,
+    co: ValueFunction,
+    value: Value,
+) -> Result<Value, RuntimeError> {
+    match &co.func {
+        InterpreterFunctionDef::BuiltIn { name, .. } if name == "coroutine" => Err(Signal::Call {
+            func: co,
+            args: vec![value],
+            then: None,
+        }
+        .into()),
+        _ => Err(RuntimeError::WrongArgumentType {
+            builtin: "resume".to_owned(),
+            index: 0,
+            expected: "coroutine",
+            found: Box::new(Value::Function(co)),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        engine::{Engine, Error},
+        interpreter::{RuntimeError, Value},
+    };
+
+    #[test]
+    fn coroutines_suspend_at_yield() {
+        let mut engine = Engine::new();
+        engine
+            .eval(
+                "let { counter; } (coroutine { start =>
+                    let { i; } (alloc 1);
+                    i `= start;
+                    while { < (* i) 4; } {
+                        let { step; } (yield (* i));
+                        i `= (+ (* i) step);
+                    };
+                })",
+            )
+            .unwrap();
+        assert!(matches!(
+            engine.eval("resume counter 1"),
+            Ok(Value::Number(1))
+        ));
+        assert!(matches!(
+            engine.eval("resume counter 2"),
+            Ok(Value::Number(3))
+        ));
+        assert!(matches!(
+            engine.eval("done? (resume counter 2)"),
+            Ok(Value::Number(1))
+        ));
+        assert!(matches!(
+            engine.eval("done? (counter 0)"),
+            Ok(Value::Number(1))
+        ));
+
+        // coroutines are iterators
+        engine
+            .eval("let { squares; } (coroutine { _ => yield 1; yield 4; yield 9; })")
+            .unwrap();
+        assert!(matches!(
+            engine.eval("sum (collect squares)"),
+            Ok(Value::Number(14))
+        ));
+        assert!(matches!(
+            engine.eval("yield 1"),
+            Err(Error::Runtime(RuntimeError::ExplicitlyRaisedMessage(_)))
+        ));
+    }
+}
@@ -0,0 +1,176 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+};
+
+use crate::{
+    interpreter::{InterpreterContext, RuntimeError},
+    permissions::Permission,
+};
+
+use super::BuiltinState;
+
+pub fn register(interpreter: &mut InterpreterContext) {
+    interpreter.register_native_fn("readfile", readfile);
+    interpreter.register_native_fn("writefile", writefile);
+    interpreter.register_native_fn("appendfile", appendfile);
+    interpreter.register_native_fn("exists", exists);
+    interpreter.register_native_fn("listdir", listdir);
+    interpreter.register_native_fn("mkdir", mkdir);
+    interpreter.register_native_fn("remove", remove);
+}
+
+/// Reads the path string at `strptr` and checks the permission to access it
+fn checked_path(
+    interpreter: &mut InterpreterContext,
+    strptr: usize,
+    permission: fn(PathBuf) -> Permission,
+) -> Result<PathBuf, RuntimeError> {
+    let path = PathBuf::from(
+        interpreter
+            .state_or_default::<BuiltinState>()
+            .read_str(strptr)?,
+    );
+    interpreter.check_permission(permission(path.clone()))?;
+    Ok(path)
+}
+
+fn readfile(interpreter: &mut InterpreterContext, path: usize) -> Result<usize, RuntimeError> {
+    let path = checked_path(interpreter, path, Permission::Read)?;
+    let bytes = fs::read(path).map_err(RuntimeError::Io)?;
+    interpreter
+        .state_or_default::<BuiltinState>()
+        .alloc_bytes(bytes)
+}
+
+/// Replaces the contents of a file, returning the number of bytes written
+fn writefile(
+    interpreter: &mut InterpreterContext,
+    path: usize,
+    content: usize,
+) -> Result<usize, RuntimeError> {
+    let path = checked_path(interpreter, path, Permission::Write)?;
+    let content = interpreter
+        .state_or_default::<BuiltinState>()
+        .read_str(content)?;
+    fs::write(path, &content).map_err(RuntimeError::Io)?;
+    Ok(content.len())
+}
+
+fn appendfile(
+    interpreter: &mut InterpreterContext,
+    path: usize,
+    content: usize,
+) -> Result<usize, RuntimeError> {
+    let path = checked_path(interpreter, path, Permission::Write)?;
+    let content = interpreter
+        .state_or_default::<BuiltinState>()
+        .read_str(content)?;
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(content.as_bytes()))
+        .map_err(RuntimeError::Io)?;
+    Ok(content.len())
+}
+
+fn exists(interpreter: &mut InterpreterContext, path: usize) -> Result<bool, RuntimeError> {
+    let path = checked_path(interpreter, path, Permission::Read)?;
+    path.try_exists().map_err(RuntimeError::Io)
+}
+
+/// Returns an array of the sorted names of the entries in a directory
+fn listdir(interpreter: &mut InterpreterContext, path: usize) -> Result<usize, RuntimeError> {
+    let path = checked_path(interpreter, path, Permission::Read)?;
+    let mut names = fs::read_dir(path)
+        .and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|e| e.file_name().to_string_lossy().into_owned()))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(RuntimeError::Io)?;
+    names.sort();
+    let state = interpreter.state_or_default::<BuiltinState>();
+    let array = state.alloc(names.len())?;
+    for (i, name) in names.iter().enumerate() {
+        let name = state.alloc_str(name)?;
+        state.set(array + i, name)?;
+    }
+    Ok(array)
+}
+
+/// Creates a directory and its missing parents
+fn mkdir(interpreter: &mut InterpreterContext, path: usize) -> Result<usize, RuntimeError> {
+    let path = checked_path(interpreter, path, Permission::Write)?;
+    fs::create_dir_all(path).map_err(RuntimeError::Io)?;
+    Ok(1)
+}
+
+/// Removes a file or an empty directory
+fn remove(interpreter: &mut InterpreterContext, path: usize) -> Result<usize, RuntimeError> {
+    let path = checked_path(interpreter, path, Permission::Write)?;
+    if path.is_dir() {
+        fs::remove_dir(path)
+    } else {
+        fs::remove_file(path)
+    }
+    .map_err(RuntimeError::Io)?;
+    Ok(1)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        builtin::BuiltinState,
+        engine::{Engine, Error},
+        interpreter::{RuntimeError, Value},
+    };
+
+    fn define_str(engine: &mut Engine, name: &str, string: &str) {
+        let strptr = engine
+            .interpreter_mut()
+            .state_or_default::<BuiltinState>()
+            .alloc_str(string)
+            .unwrap();
+        engine.define(name, Value::Number(strptr));
+    }
+
+    #[test]
+    fn files_round_trip_within_permissions() {
+        let dir = std::env::temp_dir().join("stayc-fs-test");
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut engine = Engine::new();
+        define_str(&mut engine, "dir", dir.to_str().unwrap());
+        define_str(&mut engine, "file", dir.join("a.txt").to_str().unwrap());
+        define_str(&mut engine, "text", "hi");
+        assert!(matches!(
+            engine.eval("mkdir dir"),
+            Err(Error::Runtime(RuntimeError::PermissionDenied(_)))
+        ));
+
+        let permissions = engine.interpreter_mut().permissions_mut();
+        permissions.allow_read(&dir);
+        permissions.allow_write(&dir);
+        engine
+            .eval("{ mkdir dir; writefile file text; appendfile file text; }")
+            .unwrap();
+        assert!(matches!(engine.eval("exists file"), Ok(Value::Number(1))));
+        assert!(matches!(
+            engine.eval("len (readfile file)"),
+            Ok(Value::Number(4))
+        ));
+        assert!(matches!(
+            engine.eval("len (listdir dir)"),
+            Ok(Value::Number(1))
+        ));
+        engine.eval("remove file").unwrap();
+        assert!(matches!(engine.eval("exists file"), Ok(Value::Number(0))));
+        assert!(matches!(
+            engine.eval("readfile file"),
+            Err(Error::Runtime(RuntimeError::Io(_)))
+        ));
+        engine.eval("remove dir").unwrap();
+    }
+}
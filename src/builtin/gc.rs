@@ -0,0 +1,156 @@
+use crate::{
+    interpreter::{InterpreterContext, RuntimeError},
+    machine::Coroutines,
+};
+
+use super::{memo::Memos, module::Modules, AllocationData, BuiltinState};
+
+#[derive(Debug, Default)]
+pub struct GcStats {
+    pub collections: usize,
+    pub reclaimed_allocations: usize,
+    pub reclaimed_cells: usize,
+}
+
+impl BuiltinState {
+    /// Index of the allocation containing `addr`. Interior pointers count, since
+    /// stayc code addresses elements with `ptr + offset`.
+    fn allocation_index(&self, addr: usize) -> Option<usize> {
+        // allocations are pushed with increasing start addresses
+        let index = self.heap.partition_point(|a| a.start_addr <= addr);
+        if index == 0 {
+            return None;
+        }
+        let allocation = &self.heap[index - 1];
+        if addr < allocation.end() {
+            Some(index - 1)
+        } else {
+            None
+        }
+    }
+
+    /// Conservative mark and sweep: every root and every cell of a reachable
+    /// allocation is treated as a potential pointer. Returns the number of
+    /// reclaimed allocations.
+    fn collect(&mut self, roots: Vec<usize>) -> usize {
+        let mut marked = vec![false; self.heap.len()];
+        let mut pending = roots;
+        while let Some(candidate) = pending.pop() {
+            if let Some(index) = self.allocation_index(candidate) {
+                if !marked[index] {
+                    marked[index] = true;
+                    match &self.heap[index].data {
+                        AllocationData::Cells(cells) => pending.extend_from_slice(cells),
+                        AllocationData::Map(map) => pending.extend(map.numbers()),
+                        // byte buffers hold text, never pointers
+                        AllocationData::Bytes(_) => {}
+                    }
+                }
+            }
+        }
+
+        let before = self.heap.len();
+        let mut reclaimed_cells = 0;
+        let mut marked = marked.into_iter();
+        self.heap.retain(|allocation| {
+            let keep = marked.next().unwrap_or(true);
+            if !keep {
+                reclaimed_cells += allocation.data.len();
+            }
+            keep
+        });
+        let reclaimed = before - self.heap.len();
+
+        self.gc_stats.collections += 1;
+        self.gc_stats.reclaimed_allocations += reclaimed;
+        self.gc_stats.reclaimed_cells += reclaimed_cells;
+        reclaimed
+    }
+}
+
+pub fn register(interpreter: &mut InterpreterContext) {
+    interpreter.register_native_fn("gc", gc);
+    interpreter.register_native_fn("gcstats", gcstats);
+}
+
+fn gc(interpreter: &mut InterpreterContext) -> Result<usize, RuntimeError> {
+    let mut roots = interpreter.root_numbers();
+    // imported modules stay cached even if no scope refers to them anymore
+    if let Some(modules) = interpreter.state::<Modules>() {
+        for module in modules.values() {
+            module.visit_numbers(&mut |n| roots.push(n));
+        }
+    }
+    // suspended coroutines keep their scopes
+    if let Some(coroutines) = interpreter.state::<Coroutines>() {
+        coroutines.visit_numbers(&mut |n| roots.push(n));
+    }
+    if let Some(memos) = interpreter.state::<Memos>() {
+        memos.visit_numbers(&mut |n| roots.push(n));
+    }
+    Ok(interpreter
+        .state_or_default::<BuiltinState>()
+        .collect(roots))
+}
+
+fn gcstats(interpreter: &mut InterpreterContext) -> Result<usize, RuntimeError> {
+    let state = interpreter.state_or_default::<BuiltinState>();
+    let live_cells: usize = state.heap.iter().map(|a| a.data.len()).sum();
+    let live = state.heap.len();
+    let stats = format!(
+        "collections: {}, reclaimed: {} allocations ({} cells), live: {} allocations ({} cells)",
+        state.gc_stats.collections,
+        state.gc_stats.reclaimed_allocations,
+        state.gc_stats.reclaimed_cells,
+        live,
+        live_cells
+    );
+    interpreter.println(stats)?;
+    Ok(live)
+}
+
+#[cfg(test)]
+mod test {
+    use super::BuiltinState;
+    use crate::{engine::Engine, interpreter::Value, io::MemoryIo};
+
+    #[test]
+    fn collect_keeps_reachable_allocations() {
+        let mut state = BuiltinState::default();
+        let root = state.alloc(2).unwrap();
+        let child = state.alloc(3).unwrap();
+        let garbage = state.alloc(4).unwrap();
+        state.set(root + 1, child + 2).unwrap();
+
+        assert_eq!(state.collect(vec![root]), 1);
+        assert!(state.get(child).is_ok());
+        assert!(state.get(garbage).is_err());
+        assert_eq!(state.gc_stats.reclaimed_cells, 4);
+    }
+
+    #[test]
+    fn collect_follows_interior_pointers() {
+        let mut state = BuiltinState::default();
+        let array = state.alloc(5).unwrap();
+        assert_eq!(state.collect(vec![array + 4]), 0);
+        assert_eq!(state.collect(vec![array + 5]), 1);
+    }
+
+    #[test]
+    fn collect_keeps_values_in_use_by_running_code() {
+        let mut engine = Engine::new();
+        // the first argument is only held by the pending call while `gc` runs
+        assert!(matches!(
+            engine.eval("{ let { f; } { a b => a `= 7; * a; }; f (alloc 1) (gc 0); }"),
+            Ok(Value::Number(7))
+        ));
+
+        // the iterator over the array is only held by `foreach`
+        let io = MemoryIo::new();
+        engine.interpreter_mut().set_io(io.clone());
+        engine
+            .eval("foreach (range 0 5) { e => gc 0; print e; }")
+            .unwrap();
+        assert_eq!(io.take_stdout().lines().count(), 5);
+    }
+}
@@ -0,0 +1,208 @@
+use std::fmt::Display;
+
+use crate::interpreter::{InterpreterContext, RuntimeError};
+
+use super::{cell_to_char, Allocation, AllocationData, BuiltinState, STRICT_OFFSET_BITS};
+
+const PREVIEW_LEN: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocationKind {
+    /// `usize` cells, eg. from `alloc`
+    Cells,
+    /// One byte per address, used for text
+    Bytes,
+    Map,
+}
+
+#[derive(Debug, Clone)]
+pub struct AllocationInfo {
+    pub start_addr: usize,
+    /// Number of cells, or of keys and values for a map
+    pub len: usize,
+    pub kind: AllocationKind,
+    /// The first cells of the allocation
+    pub numbers: Vec<usize>,
+    /// The first cells of the allocation decoded as text
+    pub text: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct HeapStats {
+    pub allocations: usize,
+    /// Addresses taken up by live allocations
+    pub cells: usize,
+    pub bytes: usize,
+    /// Unused addresses between allocations, left behind by the garbage collector
+    pub free_cells: usize,
+    /// Share of the used address range that is free, in percent
+    pub fragmentation: f64,
+}
+
+impl AllocationInfo {
+    fn new(allocation: &Allocation) -> Self {
+        let (numbers, text) = match &allocation.data {
+            AllocationData::Cells(cells) => {
+                let preview = &cells[..cells.len().min(PREVIEW_LEN)];
+                (
+                    preview.to_vec(),
+                    preview.iter().map(|&c| cell_to_char(c)).collect(),
+                )
+            }
+            AllocationData::Bytes(bytes) => {
+                let preview = &bytes[..bytes.len().min(PREVIEW_LEN)];
+                (
+                    preview.iter().map(|&b| b as usize).collect(),
+                    String::from_utf8_lossy(preview).into_owned(),
+                )
+            }
+            // keys and values, alternating
+            AllocationData::Map(map) => (map.numbers().take(PREVIEW_LEN).collect(), String::new()),
+        };
+        Self {
+            start_addr: allocation.start_addr,
+            len: match &allocation.data {
+                AllocationData::Map(map) => map.len() * 2,
+                data => data.len(),
+            },
+            kind: match allocation.data {
+                AllocationData::Cells(_) => AllocationKind::Cells,
+                AllocationData::Bytes(_) => AllocationKind::Bytes,
+                AllocationData::Map(_) => AllocationKind::Map,
+            },
+            numbers,
+            text,
+        }
+    }
+}
+
+impl Display for AllocationInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ellipsis = if self.len > self.numbers.len() {
+            ".."
+        } else {
+            ""
+        };
+        f.write_fmt(format_args!(
+            "{:#x} {} {} {:?}{} \"{}\"{}",
+            self.start_addr,
+            self.len,
+            match self.kind {
+                AllocationKind::Cells => "cells",
+                AllocationKind::Bytes => "bytes",
+                AllocationKind::Map => "map",
+            },
+            self.numbers,
+            ellipsis,
+            self.text.escape_debug(),
+            ellipsis
+        ))
+    }
+}
+
+impl Display for HeapStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "allocations: {}, addresses: {} ({} bytes), free cells: {}, fragmentation: {:.1}%",
+            self.allocations, self.cells, self.bytes, self.free_cells, self.fragmentation
+        ))
+    }
+}
+
+impl BuiltinState {
+    fn heap_dump(&self) -> Vec<AllocationInfo> {
+        self.heap.iter().map(AllocationInfo::new).collect()
+    }
+
+    fn heap_stats(&self) -> HeapStats {
+        let cells: usize = self.heap.iter().map(|a| a.data.len()).sum();
+        let bytes = self
+            .heap
+            .iter()
+            .map(|a| match &a.data {
+                AllocationData::Cells(cells) => cells.len() * std::mem::size_of::<usize>(),
+                AllocationData::Bytes(bytes) => bytes.len(),
+                AllocationData::Map(map) => map.len() * 2 * std::mem::size_of::<usize>(),
+            })
+            .sum();
+        let free_cells = self
+            .heap
+            .windows(2)
+            // strict allocations start a whole slot apart on purpose, the
+            // space in front of them is padding rather than free
+            .filter(|w| w[1].start_addr < 1 << STRICT_OFFSET_BITS)
+            .map(|w| w[1].start_addr - (w[0].start_addr + w[0].data.len()))
+            .sum();
+        HeapStats {
+            allocations: self.heap.len(),
+            cells,
+            bytes,
+            free_cells,
+            fragmentation: if cells + free_cells == 0 {
+                0.0
+            } else {
+                free_cells as f64 * 100.0 / (cells + free_cells) as f64
+            },
+        }
+    }
+}
+
+impl InterpreterContext {
+    /// Lists the live heap allocations, or `None` if the interpreter has no builtin heap
+    pub fn heap_dump(&self) -> Option<Vec<AllocationInfo>> {
+        self.state::<BuiltinState>().map(BuiltinState::heap_dump)
+    }
+
+    pub fn heap_stats(&self) -> Option<HeapStats> {
+        self.state::<BuiltinState>().map(BuiltinState::heap_stats)
+    }
+}
+
+pub fn register(interpreter: &mut InterpreterContext) {
+    interpreter.register_native_fn("heapdump", heapdump);
+    interpreter.register_native_fn("heapstats", heapstats);
+}
+
+fn heapdump(interpreter: &mut InterpreterContext) -> Result<usize, RuntimeError> {
+    let dump = interpreter.state_or_default::<BuiltinState>().heap_dump();
+    for allocation in &dump {
+        interpreter.println(allocation)?;
+    }
+    Ok(dump.len())
+}
+
+fn heapstats(interpreter: &mut InterpreterContext) -> Result<usize, RuntimeError> {
+    let stats = interpreter.state_or_default::<BuiltinState>().heap_stats();
+    interpreter.println(&stats)?;
+    Ok(stats.bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::BuiltinState;
+
+    #[test]
+    fn heap_stats_count_gaps_between_allocations() {
+        let mut state = BuiltinState::default();
+        state.alloc(3).unwrap();
+        state.alloc(2).unwrap();
+        state.alloc(1).unwrap();
+        state.heap.remove(1);
+        assert_eq!(
+            state.heap_stats().to_string(),
+            "allocations: 2, addresses: 4 (32 bytes), free cells: 2, fragmentation: 33.3%"
+        );
+
+        // the padding of strict slots isn't free space
+        let mut state = BuiltinState {
+            strict: true,
+            ..Default::default()
+        };
+        state.alloc(3).unwrap();
+        state.alloc(3).unwrap();
+        assert_eq!(
+            state.heap_stats().to_string(),
+            "allocations: 2, addresses: 6 (48 bytes), free cells: 0, fragmentation: 0.0%"
+        );
+    }
+}
@@ -0,0 +1,319 @@
+use std::{cell::Cell, rc::Rc};
+
+use crate::{
+    interpreter::{InterpreterContext, InterpreterFunctionDef, RuntimeError, Value, ValueFunction},
+    machine::Signal,
+    native::partial,
+    permissions::Permission,
+};
+
+use super::{AllocationData, BuiltinState};
+
+/// An iterator is a function that returns its next element when called with
+/// `0`, and `done` once it is exhausted
+pub fn register(interpreter: &mut InterpreterContext) {
+    interpreter.register_builtin("done".to_owned(), 1, |_, _| Ok(done()));
+    interpreter.register_native_fn("done?", is_done);
+    interpreter.register_native_fn("iter", iter);
+    interpreter.register_native_fn("imap", imap);
+    interpreter.register_native_fn("ifilter", ifilter);
+    interpreter.register_native_fn("take", take);
+    interpreter.register_native_fn("collect", collect);
+    interpreter.register_native_fn("lines", lines);
+    interpreter.register_native_fn("foreach", foreach);
+}
+
+/// The value returned by exhausted iterators
+pub(super) fn done() -> Value {
+    Value::Function(ValueFunction {
+        func: InterpreterFunctionDef::BuiltIn {
+            name: "done".to_owned(),
+            arg_count: 1,
+            func: Rc::new(|_, _| Ok(done())),
+        },
+        bound_context: vec![],
+        bound_variables: vec![],
+    })
+}
+
+fn is_done_value(value: &Value) -> bool {
+    matches!(
+        value,
+        Value::Function(ValueFunction {
+            func: InterpreterFunctionDef::BuiltIn { name, .. },
+            bound_variables,
+            ..
+        }) if name == "done" && bound_variables.is_empty()
+    )
+}
+
+fn is_done(_: &mut InterpreterContext, value: Value) -> Result<bool, RuntimeError> {
+    Ok(is_done_value(&value))
+}
+
+/// Pulls the next element, or `None` once the iterator is done
+fn next(
+    interpreter: &mut InterpreterContext,
+    it: &ValueFunction,
+) -> Result<Option<Value>, RuntimeError> {
+    let value = interpreter.apply(it.clone(), vec![Value::Number(0)])?;
+    Ok((!is_done_value(&value)).then_some(value))
+}
+
+impl BuiltinState {
+    /// Element `index` of the array at `addr`, `None` past its end
+    fn element(&self, addr: usize, index: usize) -> Result<Option<usize>, RuntimeError> {
+        match self.allocation(addr).map(|a| &a.data) {
+            Some(AllocationData::Cells(cells)) => Ok(cells.get(index).copied()),
+            Some(AllocationData::Bytes(bytes)) => Ok(bytes.get(index).map(|&b| b as usize)),
+            _ => Err(RuntimeError::ExplicitlyRaisedMessage("not an array")),
+        }
+    }
+}
+
+/// Iterates over the elements of an array. Iterators are returned unchanged.
+fn iter(interpreter: &mut InterpreterContext, seq: Value) -> Result<ValueFunction, RuntimeError> {
+    match seq {
+        Value::Number(array) => {
+            interpreter
+                .state_or_default::<BuiltinState>()
+                .element(array, 0)?;
+            // the position lives in the closure, the array stays visible to the collector
+            let position = Rc::new(Cell::new(0));
+            Ok(partial(
+                "array iterator",
+                move |interpreter: &mut InterpreterContext, array: usize, _: Value| {
+                    array_next(interpreter, array, &position)
+                },
+                vec![Value::Number(array)],
+            ))
+        }
+        Value::Function(it) => Ok(it),
+        found => Err(RuntimeError::WrongArgumentType {
+            builtin: "iter".to_owned(),
+            index: 0,
+            expected: "array or iterator",
+            found: Box::new(found),
+        }),
+    }
+}
+
+fn array_next(
+    interpreter: &mut InterpreterContext,
+    array: usize,
+    position: &Cell<usize>,
+) -> Result<Value, RuntimeError> {
+    let index = position.get();
+    match interpreter
+        .state_or_default::<BuiltinState>()
+        .element(array, index)?
+    {
+        Some(element) => {
+            position.set(index + 1);
+            Ok(Value::Number(element))
+        }
+        None => Ok(done()),
+    }
+}
+
+/// Applies `f` to each element when it is pulled
+fn imap(
+    interpreter: &mut InterpreterContext,
+    seq: Value,
+    f: ValueFunction,
+) -> Result<ValueFunction, RuntimeError> {
+    let it = iter(interpreter, seq)?;
+    Ok(partial(
+        "imap iterator",
+        imap_next,
+        vec![Value::Function(it), Value::Function(f)],
+    ))
+}
+
+fn imap_next(
+    interpreter: &mut InterpreterContext,
+    it: ValueFunction,
+    f: ValueFunction,
+    _: Value,
+) -> Result<Value, RuntimeError> {
+    match next(interpreter, &it)? {
+        Some(value) => interpreter.apply(f, vec![value]),
+        None => Ok(done()),
+    }
+}
+
+/// Skips the elements for which `pred` returns 0
+fn ifilter(
+    interpreter: &mut InterpreterContext,
+    seq: Value,
+    pred: ValueFunction,
+) -> Result<ValueFunction, RuntimeError> {
+    let it = iter(interpreter, seq)?;
+    Ok(partial(
+        "ifilter iterator",
+        ifilter_next,
+        vec![Value::Function(it), Value::Function(pred)],
+    ))
+}
+
+fn ifilter_next(
+    interpreter: &mut InterpreterContext,
+    it: ValueFunction,
+    pred: ValueFunction,
+    _: Value,
+) -> Result<Value, RuntimeError> {
+    while let Some(value) = next(interpreter, &it)? {
+        match interpreter.apply(pred.clone(), vec![value.clone()])? {
+            Value::Number(0) => {}
+            Value::Number(_) => return Ok(value),
+            _ => {
+                return Err(RuntimeError::ExplicitlyRaisedMessage(
+                    "expected the function to return a number",
+                ))
+            }
+        }
+    }
+    Ok(done())
+}
+
+/// Stops after at most `n` elements
+fn take(
+    interpreter: &mut InterpreterContext,
+    seq: Value,
+    n: usize,
+) -> Result<ValueFunction, RuntimeError> {
+    let it = iter(interpreter, seq)?;
+    let remaining = Rc::new(Cell::new(n));
+    Ok(partial(
+        "take iterator",
+        move |interpreter: &mut InterpreterContext, it: ValueFunction, _: Value| {
+            take_next(interpreter, it, &remaining)
+        },
+        vec![Value::Function(it)],
+    ))
+}
+
+fn take_next(
+    interpreter: &mut InterpreterContext,
+    it: ValueFunction,
+    remaining: &Cell<usize>,
+) -> Result<Value, RuntimeError> {
+    let left = remaining.get();
+    if left == 0 {
+        return Ok(done());
+    }
+    remaining.set(left - 1);
+    Ok(next(interpreter, &it)?.unwrap_or_else(done))
+}
+
+/// Pulls all remaining elements into a new array
+fn collect(interpreter: &mut InterpreterContext, seq: Value) -> Result<usize, RuntimeError> {
+    let it = iter(interpreter, seq)?;
+    interpreter.root(Value::Function(it.clone()));
+    let mut elements = vec![];
+    while let Some(value) = next(interpreter, &it)? {
+        match value {
+            Value::Number(n) => {
+                interpreter.root(value);
+                elements.push(n);
+            }
+            _ => {
+                return Err(RuntimeError::ExplicitlyRaisedMessage(
+                    "only numbers can be collected into an array",
+                ))
+            }
+        }
+    }
+    interpreter
+        .state_or_default::<BuiltinState>()
+        .push_allocation(AllocationData::Cells(elements))
+}
+
+/// Iterates over the lines of stdin, reading one line per pulled element
+fn lines(interpreter: &mut InterpreterContext, _: Value) -> Result<ValueFunction, RuntimeError> {
+    interpreter.check_permission(Permission::Stdin)?;
+    Ok(partial("lines iterator", lines_next, vec![]))
+}
+
+fn lines_next(interpreter: &mut InterpreterContext, _: Value) -> Result<Value, RuntimeError> {
+    interpreter.check_permission(Permission::Stdin)?;
+    match interpreter.io().read_line().map_err(RuntimeError::Io)? {
+        Some(line) => Ok(Value::Number(
+            interpreter
+                .state_or_default::<BuiltinState>()
+                .alloc_str(&line)?,
+        )),
+        None => Ok(done()),
+    }
+}
+
+/// Calls `func` with every element of an array or iterator, until it breaks
+fn foreach(
+    interpreter: &mut InterpreterContext,
+    seq: Value,
+    func: ValueFunction,
+) -> Result<usize, RuntimeError> {
+    let it = iter(interpreter, seq)?;
+    interpreter.root(Value::Function(it.clone()));
+    while let Some(value) = next(interpreter, &it)? {
+        match interpreter.eval_body(func.clone(), vec![value]) {
+            Err(RuntimeError::Signal(signal)) if matches!(*signal, Signal::Break) => break,
+            Err(RuntimeError::Signal(signal)) if matches!(*signal, Signal::Continue) => {}
+            result => {
+                result?;
+            }
+        }
+    }
+    Ok(0)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{engine::Engine, interpreter::Value, io::MemoryIo};
+
+    #[test]
+    fn iterators_are_lazy() {
+        let mut engine = Engine::new();
+        let io = MemoryIo::new();
+        io.push_input("3\n4\n");
+        engine.interpreter_mut().set_io(io.clone());
+        engine.interpreter_mut().permissions_mut().stdin = true;
+        engine
+            .eval("let { big; } (ifilter (range 0 10) { n => < 2 n; })")
+            .unwrap();
+        let squares = engine
+            .eval("collect (take (imap big { n => mul n n; }) 3)")
+            .unwrap();
+        let squares = match squares {
+            Value::Number(addr) => addr,
+            other => panic!("expected an array, got {:?}", other),
+        };
+        engine.define("squares", Value::Number(squares));
+        assert!(matches!(engine.eval("len squares"), Ok(Value::Number(3))));
+        assert!(matches!(
+            engine.eval("get squares 2"),
+            Ok(Value::Number(25))
+        ));
+        // the filter continues where `take` stopped pulling
+        assert!(matches!(engine.eval("big 0"), Ok(Value::Number(6))));
+        assert!(matches!(
+            engine.eval("done? (take big 0 0)"),
+            Ok(Value::Number(1))
+        ));
+
+        // iterating over an array doesn't allocate
+        engine.eval("let { xs; } ([ 1 2 3 ])").unwrap();
+        let allocations = engine.interpreter().heap_dump().unwrap().len();
+        engine.eval("foreach (take xs 2) { x => + x 1; }").unwrap();
+        assert_eq!(engine.interpreter().heap_dump().unwrap().len(), allocations);
+
+        engine
+            .eval("foreach (lines 0) { line => printstr line; }")
+            .unwrap();
+        assert_eq!(io.take_stdout(), "3\n4\n");
+        assert!(matches!(
+            engine.eval("done? (lines 0 0)"),
+            Ok(Value::Number(1))
+        ));
+    }
+}
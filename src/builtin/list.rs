@@ -0,0 +1,320 @@
+use crate::interpreter::{InterpreterContext, RuntimeError, Value, ValueFunction};
+
+use super::{string, AllocationData, BuiltinState};
+
+/// Returned by `find` and `indexof` if nothing matches
+const NOT_FOUND: usize = usize::MAX;
+
+pub fn register(interpreter: &mut InterpreterContext) {
+    interpreter.register_native_fn("sort", sort);
+    interpreter.register_native_fn("filter", filter);
+    interpreter.register_native_fn("fold", fold);
+    interpreter.register_native_fn("any", any);
+    interpreter.register_native_fn("all", all);
+    interpreter.register_native_fn("find", find);
+    interpreter.register_native("indexof", indexof);
+    interpreter.register_native("reverse", reverse);
+    interpreter.register_native("range", range);
+    interpreter.register_native("zip", zip);
+    interpreter.register_native("sum", sum);
+}
+
+impl BuiltinState {
+    /// Copies the elements of the array at `addr`, so callbacks can't change
+    /// them while they are processed
+    fn elements(&self, addr: usize) -> Result<Vec<usize>, RuntimeError> {
+        match self.allocation(addr).map(|a| &a.data) {
+            Some(AllocationData::Cells(cells)) => Ok(cells.clone()),
+            Some(AllocationData::Bytes(bytes)) => Ok(bytes.iter().map(|&b| b as usize).collect()),
+            _ => Err(RuntimeError::ExplicitlyRaisedMessage("not an array")),
+        }
+    }
+
+    fn alloc_array(&mut self, elements: Vec<usize>) -> Result<usize, RuntimeError> {
+        self.push_allocation(AllocationData::Cells(elements))
+    }
+}
+
+/// Copies the elements for calling back into stayc with them, rooting them
+/// in case the callback overwrites the array
+fn elements(interpreter: &mut InterpreterContext, addr: usize) -> Result<Vec<usize>, RuntimeError> {
+    let elements = interpreter
+        .state_or_default::<BuiltinState>()
+        .elements(addr)?;
+    for &element in &elements {
+        interpreter.root(Value::Number(element));
+    }
+    Ok(elements)
+}
+
+fn alloc_array(
+    interpreter: &mut InterpreterContext,
+    elements: Vec<usize>,
+) -> Result<usize, RuntimeError> {
+    interpreter
+        .state_or_default::<BuiltinState>()
+        .alloc_array(elements)
+}
+
+/// Calls a stayc function with numeric arguments, expecting a number back
+fn call(
+    interpreter: &mut InterpreterContext,
+    func: &ValueFunction,
+    args: &[usize],
+) -> Result<usize, RuntimeError> {
+    let args = args.iter().map(|&arg| Value::Number(arg)).collect();
+    match interpreter.apply(func.clone(), args)? {
+        Value::Number(n) => Ok(n),
+        _ => Err(RuntimeError::ExplicitlyRaisedMessage(
+            "expected the function to return a number",
+        )),
+    }
+}
+
+/// Stable merge sort, `less a b` returns non-zero if `a` goes before `b`
+fn sort(
+    interpreter: &mut InterpreterContext,
+    array: usize,
+    less: ValueFunction,
+) -> Result<usize, RuntimeError> {
+    fn merge_sort(
+        interpreter: &mut InterpreterContext,
+        elements: Vec<usize>,
+        less: &ValueFunction,
+    ) -> Result<Vec<usize>, RuntimeError> {
+        if elements.len() <= 1 {
+            return Ok(elements);
+        }
+        let mut left = elements;
+        let right = left.split_off(left.len() / 2);
+        let left = merge_sort(interpreter, left, less)?;
+        let right = merge_sort(interpreter, right, less)?;
+        let mut merged = Vec::with_capacity(left.len() + right.len());
+        let (mut left, mut right) = (left.into_iter().peekable(), right.into_iter().peekable());
+        while let (Some(&l), Some(&r)) = (left.peek(), right.peek()) {
+            // take from the right only if it's strictly less, to keep equal elements in order
+            if call(interpreter, less, &[r, l])? != 0 {
+                merged.push(r);
+                right.next();
+            } else {
+                merged.push(l);
+                left.next();
+            }
+        }
+        merged.extend(left);
+        merged.extend(right);
+        Ok(merged)
+    }
+
+    let elements = elements(interpreter, array)?;
+    let sorted = merge_sort(interpreter, elements, &less)?;
+    alloc_array(interpreter, sorted)
+}
+
+fn filter(
+    interpreter: &mut InterpreterContext,
+    array: usize,
+    predicate: ValueFunction,
+) -> Result<usize, RuntimeError> {
+    let mut kept = Vec::new();
+    for element in elements(interpreter, array)? {
+        if call(interpreter, &predicate, &[element])? != 0 {
+            kept.push(element);
+        }
+    }
+    alloc_array(interpreter, kept)
+}
+
+/// Calls `func acc element` for every element, starting with `init` as the accumulator
+fn fold(
+    interpreter: &mut InterpreterContext,
+    array: usize,
+    init: usize,
+    func: ValueFunction,
+) -> Result<usize, RuntimeError> {
+    let mut acc = init;
+    for element in elements(interpreter, array)? {
+        acc = call(interpreter, &func, &[acc, element])?;
+        interpreter.root(Value::Number(acc));
+    }
+    Ok(acc)
+}
+
+fn any(
+    interpreter: &mut InterpreterContext,
+    array: usize,
+    predicate: ValueFunction,
+) -> Result<bool, RuntimeError> {
+    for element in elements(interpreter, array)? {
+        if call(interpreter, &predicate, &[element])? != 0 {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn all(
+    interpreter: &mut InterpreterContext,
+    array: usize,
+    predicate: ValueFunction,
+) -> Result<bool, RuntimeError> {
+    for element in elements(interpreter, array)? {
+        if call(interpreter, &predicate, &[element])? == 0 {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// `find array predicate` is the index of the first element the predicate
+/// holds for, `find string needle` the index of a substring in a byte buffer
+fn find(
+    interpreter: &mut InterpreterContext,
+    addr: usize,
+    by: Value,
+) -> Result<usize, RuntimeError> {
+    match by {
+        Value::Function(predicate) => {
+            for (index, element) in elements(interpreter, addr)?.into_iter().enumerate() {
+                if call(interpreter, &predicate, &[element])? != 0 {
+                    return Ok(index);
+                }
+            }
+            Ok(NOT_FOUND)
+        }
+        // decoded strings can't be told apart from arrays, so substrings are
+        // only searched for in byte buffers
+        Value::Number(needle) if is_bytes(interpreter, addr) => {
+            string::find(interpreter.state_or_default::<BuiltinState>(), addr, needle)
+        }
+        found => Err(RuntimeError::WrongArgumentType {
+            builtin: "find".to_owned(),
+            index: 1,
+            expected: "function or string",
+            found: Box::new(found),
+        }),
+    }
+}
+
+fn is_bytes(interpreter: &mut InterpreterContext, addr: usize) -> bool {
+    matches!(
+        interpreter
+            .state_or_default::<BuiltinState>()
+            .allocation(addr)
+            .map(|a| &a.data),
+        Some(AllocationData::Bytes(_))
+    )
+}
+
+fn indexof(state: &mut BuiltinState, array: usize, value: usize) -> Result<usize, RuntimeError> {
+    Ok(state
+        .elements(array)?
+        .into_iter()
+        .position(|element| element == value)
+        .unwrap_or(NOT_FOUND))
+}
+
+fn reverse(state: &mut BuiltinState, array: usize) -> Result<usize, RuntimeError> {
+    let mut elements = state.elements(array)?;
+    elements.reverse();
+    state.alloc_array(elements)
+}
+
+/// The numbers from `from` up to (excluding) `to`
+fn range(state: &mut BuiltinState, from: usize, to: usize) -> Result<usize, RuntimeError> {
+    state.alloc_array((from..to).collect())
+}
+
+/// An array of pairs, which are arrays of two elements, as long as the shorter array
+fn zip(state: &mut BuiltinState, a: usize, b: usize) -> Result<usize, RuntimeError> {
+    let pairs = state
+        .elements(a)?
+        .into_iter()
+        .zip(state.elements(b)?)
+        .map(|(a, b)| state.alloc_array(vec![a, b]))
+        .collect::<Result<_, _>>()?;
+    state.alloc_array(pairs)
+}
+
+/// Wraps around on overflow, like `+`
+fn sum(state: &mut BuiltinState, array: usize) -> Result<usize, RuntimeError> {
+    Ok(state
+        .elements(array)?
+        .into_iter()
+        .fold(0, usize::wrapping_add))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        builtin::BuiltinState,
+        engine::{Engine, Error},
+        interpreter::{RuntimeError, Value},
+    };
+
+    fn array(engine: &mut Engine, source: &str) -> Vec<usize> {
+        match engine.eval(source) {
+            Ok(Value::Number(addr)) => engine
+                .interpreter_mut()
+                .state_or_default::<BuiltinState>()
+                .elements(addr)
+                .unwrap(),
+            other => panic!("expected an array, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn higher_order_builtins() {
+        let mut engine = Engine::new();
+        engine.eval("let { xs; } ([ 3 1 4 1 5 ])").unwrap();
+        assert_eq!(array(&mut engine, "sort xs <"), vec![1, 1, 3, 4, 5]);
+        assert_eq!(array(&mut engine, "sort xs >"), vec![5, 4, 3, 1, 1]);
+        assert_eq!(
+            array(&mut engine, "filter xs { x => > x 2; }"),
+            vec![3, 4, 5]
+        );
+        assert_eq!(array(&mut engine, "reverse (range 0 3)"), vec![2, 1, 0]);
+        assert!(matches!(
+            engine.eval("fold xs 0 { acc x => + acc x; }"),
+            Ok(Value::Number(14))
+        ));
+        assert!(matches!(engine.eval("sum xs"), Ok(Value::Number(14))));
+        assert!(matches!(
+            engine.eval("find xs 3"),
+            Err(Error::Runtime(RuntimeError::WrongArgumentType {
+                index: 1,
+                ..
+            }))
+        ));
+        // arithmetic wraps around on overflow
+        assert!(matches!(
+            engine.eval("+ 18446744073709551615 3"),
+            Ok(Value::Number(2))
+        ));
+        assert!(matches!(
+            engine.eval("mul 18446744073709551615 2"),
+            Ok(Value::Number(18446744073709551614))
+        ));
+        assert!(matches!(
+            engine.eval("sum ([ 18446744073709551615 3 ])"),
+            Ok(Value::Number(2))
+        ));
+        assert!(matches!(
+            engine.eval("find xs { x => > x 3; }"),
+            Ok(Value::Number(2))
+        ));
+        assert!(matches!(
+            engine.eval("indexof xs 7"),
+            Ok(Value::Number(usize::MAX))
+        ));
+        assert!(matches!(
+            engine.eval("all xs { x => > x 0; }"),
+            Ok(Value::Number(1))
+        ));
+        assert!(matches!(
+            engine.eval("any xs { x => > x 5; }"),
+            Ok(Value::Number(0))
+        ));
+        assert_eq!(array(&mut engine, "* (zip xs (range 0 2))"), vec![3, 0]);
+    }
+}
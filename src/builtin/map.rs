@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+
+use crate::interpreter::{InterpreterContext, RuntimeError};
+
+use super::{AllocationData, BuiltinState};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum MapKey {
+    Number(usize),
+    /// Byte buffer keys are compared by their text
+    Text(String),
+}
+
+/// Entries by key, storing the key as it was passed next to the value
+#[derive(Debug, Default)]
+pub struct Map(HashMap<MapKey, (usize, usize)>);
+
+impl Map {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// The keys and values, for tracing and inspecting the heap
+    pub fn numbers(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.values().flat_map(|&(key, value)| [key, value])
+    }
+}
+
+pub fn register(interpreter: &mut InterpreterContext) {
+    interpreter.register_native("mapnew", mapnew);
+    interpreter.register_native("mapset", mapset);
+    interpreter.register_native("mapget", mapget);
+    interpreter.register_native("maphas", maphas);
+    interpreter.register_native("mapdel", mapdel);
+    interpreter.register_native("mapkeys", mapkeys);
+    interpreter.register_native("maplen", maplen);
+}
+
+impl BuiltinState {
+    /// Numbers aren't tagged as pointers, so any number that happens to be the
+    /// address of a byte buffer is a text key
+    fn key(&self, key: usize) -> MapKey {
+        match self.allocation(key).map(|a| &a.data) {
+            Some(AllocationData::Bytes(bytes)) => {
+                MapKey::Text(String::from_utf8_lossy(bytes).into_owned())
+            }
+            _ => MapKey::Number(key),
+        }
+    }
+
+    fn map(&self, addr: usize) -> Result<&Map, RuntimeError> {
+        match self.allocation(addr).map(|a| &a.data) {
+            Some(AllocationData::Map(map)) => Ok(map),
+            _ => Err(RuntimeError::ExplicitlyRaisedMessage("not a map")),
+        }
+    }
+
+    fn map_mut(&mut self, addr: usize) -> Result<&mut Map, RuntimeError> {
+        match self
+            .heap
+            .iter_mut()
+            .find(|a| a.start_addr == addr)
+            .map(|a| &mut a.data)
+        {
+            Some(AllocationData::Map(map)) => Ok(map),
+            _ => Err(RuntimeError::ExplicitlyRaisedMessage("not a map")),
+        }
+    }
+}
+
+fn mapnew(state: &mut BuiltinState) -> Result<usize, RuntimeError> {
+    state.push_allocation(AllocationData::Map(Map::default()))
+}
+
+fn mapset(
+    state: &mut BuiltinState,
+    map: usize,
+    key: usize,
+    value: usize,
+) -> Result<usize, RuntimeError> {
+    let map_key = state.key(key);
+    state.map_mut(map)?.0.insert(map_key, (key, value));
+    Ok(1)
+}
+
+/// The value stored for `key`, or 0 if there is none
+fn mapget(state: &mut BuiltinState, map: usize, key: usize) -> Result<usize, RuntimeError> {
+    let key = state.key(key);
+    Ok(state.map(map)?.0.get(&key).map_or(0, |&(_, value)| value))
+}
+
+fn maphas(state: &mut BuiltinState, map: usize, key: usize) -> Result<bool, RuntimeError> {
+    let key = state.key(key);
+    Ok(state.map(map)?.0.contains_key(&key))
+}
+
+/// Removes the entry for `key`, returning whether there was one
+fn mapdel(state: &mut BuiltinState, map: usize, key: usize) -> Result<bool, RuntimeError> {
+    let key = state.key(key);
+    Ok(state.map_mut(map)?.0.remove(&key).is_some())
+}
+
+/// Returns an array of the keys, numbers in ascending order before strings in
+/// lexical order
+fn mapkeys(state: &mut BuiltinState, map: usize) -> Result<usize, RuntimeError> {
+    let mut entries: Vec<_> = state.map(map)?.0.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    let keys = entries.into_iter().map(|(_, &(key, _))| key).collect();
+    state.push_allocation(AllocationData::Cells(keys))
+}
+
+fn maplen(state: &mut BuiltinState, map: usize) -> Result<usize, RuntimeError> {
+    Ok(state.map(map)?.len())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{builtin::BuiltinState, engine::Engine, interpreter::Value};
+
+    #[test]
+    fn string_keys_compare_by_text() {
+        let mut engine = Engine::new();
+        let state = engine.interpreter_mut().state_or_default::<BuiltinState>();
+        let key = state.alloc_str("key").unwrap();
+        let same_key = state.alloc_str("key").unwrap();
+        engine.define("key", Value::Number(key));
+        engine.define("same", Value::Number(same_key));
+        engine
+            .eval("{ let { m; } (mapnew 0); mapset m key 3; mapset m 7 4; mapset m same 5; }")
+            .unwrap();
+        assert!(matches!(engine.eval("maplen m"), Ok(Value::Number(2))));
+        assert!(matches!(engine.eval("mapget m key"), Ok(Value::Number(5))));
+        assert!(matches!(engine.eval("* (mapkeys m)"), Ok(Value::Number(7))));
+        assert!(matches!(engine.eval("mapdel m 7"), Ok(Value::Number(1))));
+        assert!(matches!(engine.eval("maphas m 7"), Ok(Value::Number(0))));
+        assert!(matches!(engine.eval("mapget m 7"), Ok(Value::Number(0))));
+        engine.eval("gc 0").unwrap();
+        assert!(matches!(engine.eval("maphas m same"), Ok(Value::Number(1))));
+    }
+
+    #[test]
+    fn numbers_at_string_addresses_are_text_keys() {
+        let mut engine = Engine::new();
+        let state = engine.interpreter_mut().state_or_default::<BuiltinState>();
+        let key = state.alloc_str("key").unwrap();
+        engine.define("key", Value::Number(key));
+        engine
+            .eval("{ let { m; } (mapnew 0); mapset m key 1; }")
+            .unwrap();
+        // a number that is the address of a string is that string's key
+        assert!(matches!(
+            engine.eval(&format!("mapget m {}", key)),
+            Ok(Value::Number(1))
+        ));
+        assert!(matches!(
+            engine.eval(&format!("maphas m {}", key + 1)),
+            Ok(Value::Number(0))
+        ));
+    }
+}
@@ -0,0 +1,237 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::{Rc, Weak},
+};
+
+use crate::{
+    interpreter::{
+        InterpreterContext, InterpreterFunctionDef, Record, RuntimeError, Value, ValueFunction,
+    },
+    machine::Signal,
+};
+
+#[derive(Default)]
+struct Cache {
+    results: HashMap<Vec<usize>, Value>,
+    hits: usize,
+    misses: usize,
+}
+
+/// The caches of the memoized functions, by id. A cache is owned by the
+/// closures of its function, so it goes away with the last copy of it.
+#[derive(Default)]
+pub(crate) struct Memos {
+    next_id: usize,
+    caches: HashMap<usize, Weak<RefCell<Cache>>>,
+}
+
+impl Memos {
+    fn insert(&mut self, cache: &Rc<RefCell<Cache>>) -> usize {
+        self.caches.retain(|_, cache| cache.strong_count() > 0);
+        let id = self.next_id;
+        self.next_id += 1;
+        self.caches.insert(id, Rc::downgrade(cache));
+        id
+    }
+
+    /// Visits the numbers in cached arguments and results, which may point
+    /// into the heap. Arguments are cached by value, so the allocations they
+    /// point to have to be kept for their addresses not to be reused.
+    pub(crate) fn visit_numbers(&self, visit: &mut impl FnMut(usize)) {
+        for cache in self.caches.values().filter_map(Weak::upgrade) {
+            for (key, value) in &cache.borrow().results {
+                key.iter().copied().for_each(&mut *visit);
+                value.visit_numbers(visit);
+            }
+        }
+    }
+}
+
+pub fn register(interpreter: &mut InterpreterContext) {
+    interpreter.register_native_fn("memo", memo);
+    interpreter.register_native_fn("memostats", memostats);
+    interpreter.register_native_fn("memoclear", memoclear);
+}
+
+/// Wraps `func` into a function taking the same arguments, which caches its
+/// results by the arguments if they are all numbers. Arguments already bound
+/// to `func` are part of it, so they aren't part of the key. The cache keeps
+/// the allocations its arguments and results point to from being collected.
+fn memo(interpreter: &mut InterpreterContext, func: ValueFunction) -> Result<Value, RuntimeError> {
+    let arg_count = match &func.func {
+        InterpreterFunctionDef::BuiltIn { arg_count, .. } => *arg_count,
+        InterpreterFunctionDef::FunctionDef { name: _, def } => def.arg_tokens.len(),
+    };
+    let cache = Rc::new(RefCell::new(Cache::default()));
+    let id = interpreter.state_or_default::<Memos>().insert(&cache);
+    Ok(Value::Function(ValueFunction {
+        func: InterpreterFunctionDef::BuiltIn {
+            name: "memo".to_owned(),
+            // the id of the cache and `func` are bound first, so partial
+            // applications share them and the garbage collector sees `func`
+            arg_count: 2 + arg_count.saturating_sub(func.bound_variables.len()),
+            func: Rc::new(move |_, mut bound| {
+                let args = bound.split_off(2);
+                let Some(Value::Function(func)) = bound.pop() else {
+                    unreachable!("memo binds the function it wraps");
+                };
+                let key = args
+                    .iter()
+                    .map(|arg| match arg {
+                        Value::Number(n) => Some(*n),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>();
+                let then = match key {
+                    Some(key) => {
+                        let mut entry = cache.borrow_mut();
+                        if let Some(result) = entry.results.get(&key).cloned() {
+                            entry.hits += 1;
+                            return Ok(result);
+                        }
+                        entry.misses += 1;
+                        Some(store(cache.clone(), key))
+                    }
+                    None => None,
+                };
+                // called by the evaluator, so deep recursion doesn't grow the Rust stack
+                Err(Signal::Call { func, args, then }.into())
+            }),
+        },
+        bound_context: vec![],
+        bound_variables: vec![Value::Number(id), Value::Function(func)],
+    }))
+}
+
+/// A function caching its argument as the result for `key`, and returning it
+fn store(cache: Rc<RefCell<Cache>>, key: Vec<usize>) -> ValueFunction {
+    ValueFunction {
+        func: InterpreterFunctionDef::BuiltIn {
+            name: "memo result".to_owned(),
+            arg_count: 1,
+            func: Rc::new(move |_, mut args| {
+                let result = args.remove(0);
+                cache
+                    .borrow_mut()
+                    .results
+                    .insert(key.clone(), result.clone());
+                Ok(result)
+            }),
+        },
+        bound_context: vec![],
+        bound_variables: vec![],
+    }
+}
+
+/// The cache of a memoized function
+fn cache(
+    interpreter: &mut InterpreterContext,
+    builtin: &str,
+    func: &ValueFunction,
+) -> Result<Rc<RefCell<Cache>>, RuntimeError> {
+    let cache = match (&func.func, func.bound_variables.first()) {
+        (InterpreterFunctionDef::BuiltIn { name, .. }, Some(Value::Number(id)))
+            if name == "memo" =>
+        {
+            interpreter
+                .state_or_default::<Memos>()
+                .caches
+                .get(id)
+                .and_then(Weak::upgrade)
+        }
+        _ => None,
+    };
+    cache.ok_or_else(|| RuntimeError::WrongArgumentType {
+        builtin: builtin.to_owned(),
+        index: 0,
+        expected: "memoized function",
+        found: Box::new(Value::Function(func.clone())),
+    })
+}
+
+/// A record of the `hits` and `misses` of the cache and the number of cached results
+fn memostats(
+    interpreter: &mut InterpreterContext,
+    func: ValueFunction,
+) -> Result<Record, RuntimeError> {
+    let cache = cache(interpreter, "memostats", &func)?;
+    let cache = cache.borrow();
+    Ok(Record::new(vec![
+        ("hits".to_owned(), Value::Number(cache.hits)),
+        ("misses".to_owned(), Value::Number(cache.misses)),
+        ("size".to_owned(), Value::Number(cache.results.len())),
+    ]))
+}
+
+/// Drops the cached results, returning how many there were
+fn memoclear(
+    interpreter: &mut InterpreterContext,
+    func: ValueFunction,
+) -> Result<usize, RuntimeError> {
+    let cache = cache(interpreter, "memoclear", &func)?;
+    let results = std::mem::take(&mut cache.borrow_mut().results);
+    Ok(results.len())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{engine::Engine, interpreter::Value};
+
+    #[test]
+    fn memo_caches_by_arguments() {
+        let mut engine = Engine::new();
+        engine
+            .eval("let { fib; } (memo { n => if (< n 2) { n; } { + (fib (- n 1)) (fib (- n 2)); }; })")
+            .unwrap();
+        assert!(matches!(
+            engine.eval("fib 90"),
+            Ok(Value::Number(2880067194370816120))
+        ));
+        assert!(matches!(
+            engine.eval("field (memostats fib) { size; }"),
+            Ok(Value::Number(91))
+        ));
+        assert!(matches!(
+            engine.eval("field (memostats fib) { misses; }"),
+            Ok(Value::Number(91))
+        ));
+
+        engine
+            .eval("let { add; } (memo { a b => + a b; })")
+            .unwrap();
+        engine.eval("let { add1; } (add 1)").unwrap();
+        assert!(matches!(engine.eval("add1 2"), Ok(Value::Number(3))));
+        assert!(matches!(engine.eval("add 1 2"), Ok(Value::Number(3))));
+        assert!(matches!(
+            engine.eval("field (memostats add1) { hits; }"),
+            Ok(Value::Number(1))
+        ));
+        assert!(matches!(engine.eval("memoclear add"), Ok(Value::Number(1))));
+        assert!(matches!(
+            engine.eval("field (memostats add) { size; }"),
+            Ok(Value::Number(0))
+        ));
+    }
+
+    #[test]
+    fn memo_keeps_its_function_and_cache_alive() {
+        let mut engine = Engine::new();
+        engine
+            .eval("let { mk; } { _ => let { p; } (alloc 1); p `= 41; memo ({ q x => + (* q) x; } p); }")
+            .unwrap();
+        engine.eval("let { m; } (mk 0)").unwrap();
+        engine.eval("gc 0").unwrap();
+        assert!(matches!(engine.eval("m 1"), Ok(Value::Number(42))));
+    }
+
+    #[test]
+    fn unreachable_memos_release_their_cache() {
+        let mut engine = Engine::new();
+        engine.eval("gc 0").unwrap();
+        engine
+            .eval("{ let { mk; } { _ => let { m; } (memo { x => alloc 1; }); m 0; 0; }; mk 0; }")
+            .unwrap();
+        assert!(matches!(engine.eval("gc 0"), Ok(Value::Number(1))));
+    }
+}
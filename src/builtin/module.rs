@@ -0,0 +1,203 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    ast_parser::{FunctionDef, ProgramAST},
+    interpreter::{InterpreterContext, InterpreterFunctionDef, RuntimeError, Value, ValueFunction},
+    permissions::Permission,
+};
+
+use super::BuiltinState;
+
+/// Imported modules by canonical path, and the files currently being evaluated
+#[derive(Default)]
+pub(crate) struct Modules {
+    loaded: HashMap<PathBuf, Value>,
+    loading: Vec<PathBuf>,
+}
+
+impl Modules {
+    pub(crate) fn values(&self) -> impl Iterator<Item = &Value> {
+        self.loaded.values()
+    }
+}
+
+pub fn register(interpreter: &mut InterpreterContext) {
+    interpreter.register_builtin("import".to_owned(), 1, |interpreter, args| {
+        match &args[0] {
+            // import { lib/list; } binds the namespace of lib/list.st to `list`
+            Value::Function(ValueFunction {
+                func: InterpreterFunctionDef::FunctionDef { name: _, def },
+                bound_context: _,
+                bound_variables: _,
+            }) => match def.block.first() {
+                Some(ProgramAST::FunctionRef { token }) => {
+                    let path = PathBuf::from(format!("{}.st", token));
+                    let name = token.rsplit('/').next().unwrap_or(token).to_owned();
+                    let namespace = import(interpreter, &path)?;
+                    interpreter
+                        .function_context
+                        .back_mut()
+                        .unwrap()
+                        .0
+                        .push((name, namespace.clone()));
+                    Ok(namespace)
+                }
+                _ => Err(RuntimeError::ExplicitlyRaisedMessage(
+                    "import needs a function containing one token",
+                )),
+            },
+            &Value::Number(strptr) => {
+                let path = PathBuf::from(
+                    interpreter
+                        .state_or_default::<BuiltinState>()
+                        .read_str(strptr)?,
+                );
+                import(interpreter, &path)
+            }
+            _ => Err(RuntimeError::ExplicitlyRaisedMessage(
+                "import needs a token or a path string",
+            )),
+        }
+    });
+}
+
+/// Evaluates the module at `path`, relative to the file being evaluated, or
+/// returns it from the cache if it was imported before
+fn import(interpreter: &mut InterpreterContext, path: &Path) -> Result<Value, RuntimeError> {
+    let modules = interpreter.state_or_default::<Modules>();
+    let path = match modules.loading.last().and_then(|file| file.parent()) {
+        Some(dir) => dir.join(path),
+        None => path.to_owned(),
+    };
+    let path = path.canonicalize().map_err(RuntimeError::Io)?;
+    interpreter.check_permission(Permission::Read(path.clone()))?;
+    let modules = interpreter.state_or_default::<Modules>();
+    if let Some(namespace) = modules.loaded.get(&path) {
+        return Ok(namespace.clone());
+    }
+    if modules.loading.contains(&path) {
+        return Err(RuntimeError::ImportCycle(path));
+    }
+
+    let source = std::fs::read_to_string(&path).map_err(RuntimeError::Io)?;
+    let ast = ProgramAST::parse_source(&source)
+        .map_err(|error| RuntimeError::ImportParsing(path.clone(), error))?;
+    let depth = interpreter.function_context.len();
+    enter_file(interpreter, path.clone());
+    let result = interpreter.run_anonym_func(ast, vec![], false);
+    leave_file(interpreter);
+    let bindings = if result.is_ok() && interpreter.function_context.len() > depth {
+        interpreter
+            .function_context
+            .pop_back()
+            .map(|context| context.0)
+    } else {
+        None
+    };
+    while interpreter.function_context.len() > depth {
+        interpreter.function_context.pop_back();
+    }
+    result?;
+
+    let namespace = namespace(bindings.unwrap_or_default());
+    interpreter
+        .state_or_default::<Modules>()
+        .loaded
+        .insert(path, namespace.clone());
+    Ok(namespace)
+}
+
+/// Makes `path` the file relative imports are resolved against, until the
+/// matching `leave_file`
+pub(crate) fn enter_file(interpreter: &mut InterpreterContext, path: PathBuf) {
+    interpreter.state_or_default::<Modules>().loading.push(path);
+}
+
+pub(crate) fn leave_file(interpreter: &mut InterpreterContext) {
+    interpreter.state_or_default::<Modules>().loading.pop();
+}
+
+/// Bundles the top level bindings of a module into a function value whose
+/// members are accessed as `namespace.name`. Functions of the module get the
+/// module's bindings bound, so they still see each other when called elsewhere.
+fn namespace(bindings: Vec<(String, Value)>) -> Value {
+    let members = bindings
+        .iter()
+        .map(|(name, value)| {
+            let value = match value {
+                Value::Function(func) => {
+                    let mut func = func.clone();
+                    // bound by `bind` takes precedence over the module
+                    let mut context = bindings.clone();
+                    context.append(&mut func.bound_context);
+                    func.bound_context = context;
+                    Value::Function(func)
+                }
+                value => value.clone(),
+            };
+            (name.clone(), value)
+        })
+        .collect();
+    Value::Function(ValueFunction {
+        func: InterpreterFunctionDef::FunctionDef {
+            name: "module".to_owned(),
+            def: FunctionDef {
+                arg_tokens: vec![],
+                block: vec![],
+            },
+        },
+        bound_context: members,
+        bound_variables: vec![],
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        engine::{Engine, Error},
+        interpreter::{RuntimeError, Value},
+    };
+
+    #[test]
+    fn imports_are_cached_namespaces() {
+        let dir = std::env::temp_dir().join("stayc-module-test");
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        std::fs::write(
+            dir.join("lib/list.st"),
+            "{ import { util; }; let { double; } { a => util.twice a; }; let { cell; } (alloc 1); }",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("lib/util.st"),
+            "{ let { twice; } { a => + a a; }; }",
+        )
+        .unwrap();
+        std::fs::write(dir.join("lib/cycle.st"), "{ import { cycle; }; }").unwrap();
+        std::fs::write(
+            dir.join("main.st"),
+            "{ import { lib/list; }; let { again; } (import { lib/list; }); }",
+        )
+        .unwrap();
+
+        let mut engine = Engine::new();
+        assert!(matches!(
+            engine.eval_file(dir.join("main.st")),
+            Err(Error::Runtime(RuntimeError::PermissionDenied(_)))
+        ));
+        engine.interpreter_mut().permissions_mut().allow_read(&dir);
+        engine.eval_file(dir.join("main.st")).unwrap();
+        assert!(matches!(engine.eval("list.double 4"), Ok(Value::Number(8))));
+        // evaluated once, so both imports share the allocation
+        assert!(matches!(
+            engine.eval("- list.cell again.cell"),
+            Ok(Value::Number(0))
+        ));
+        assert!(matches!(
+            engine.eval_file(dir.join("lib/cycle.st")),
+            Err(Error::Runtime(RuntimeError::ImportCycle(_)))
+        ));
+    }
+}
@@ -0,0 +1,172 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use crate::{
+    interpreter::{InterpreterContext, RuntimeError, Value, ValueFunction},
+    native::partial,
+    permissions::Permission,
+};
+
+use super::{AllocationData, BuiltinState};
+
+pub fn register(interpreter: &mut InterpreterContext) {
+    interpreter.register_native_fn("exec", exec);
+    interpreter.register_native_fn("run", run);
+    interpreter.register_native_fn("spawn", spawn);
+}
+
+/// Reads an array of string pointers, where 0 is an empty array
+fn read_str_array(state: &BuiltinState, addr: usize) -> Result<Vec<String>, RuntimeError> {
+    if addr == 0 {
+        return Ok(vec![]);
+    }
+    match state.allocation(addr).map(|a| &a.data) {
+        Some(AllocationData::Cells(cells)) => {
+            cells.iter().map(|&cell| state.read_str(cell)).collect()
+        }
+        _ => Err(RuntimeError::ExplicitlyRaisedMessage(
+            "expected an array of strings",
+        )),
+    }
+}
+
+/// Runs a shell command and returns its stdout
+fn exec(interpreter: &mut InterpreterContext, strptr: usize) -> Result<usize, RuntimeError> {
+    interpreter.check_permission(Permission::Process)?;
+    let state = interpreter.state_or_default::<BuiltinState>();
+    let command = state.read_str(strptr)?;
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .output()
+        .map_err(RuntimeError::Io)?;
+    state.alloc_str(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn run(
+    interpreter: &mut InterpreterContext,
+    program: usize,
+    args: usize,
+) -> Result<ValueFunction, RuntimeError> {
+    spawn(interpreter, program, args, 0, 0)
+}
+
+/// Runs `program` with the strings in `args` and waits for it to exit. `stdin`
+/// is a string written to the program's stdin and `env` an array of `KEY=VALUE`
+/// strings added to its environment, both can be 0.
+fn spawn(
+    interpreter: &mut InterpreterContext,
+    program: usize,
+    args: usize,
+    stdin: usize,
+    env: usize,
+) -> Result<ValueFunction, RuntimeError> {
+    interpreter.check_permission(Permission::Process)?;
+    let state = interpreter.state_or_default::<BuiltinState>();
+    let mut command = Command::new(state.read_str(program)?);
+    command.args(read_str_array(state, args)?);
+    for variable in read_str_array(state, env)? {
+        let (key, value) = variable.split_once('=').unwrap_or((&variable, ""));
+        command.env(key, value);
+    }
+    let input = match stdin {
+        0 => None,
+        strptr => Some(state.read_str(strptr)?),
+    };
+    command
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let mut child = command.spawn().map_err(RuntimeError::Io)?;
+    if let (Some(input), Some(mut pipe)) = (input, child.stdin.take()) {
+        // written from another thread so a child filling its stdout can't block us
+        std::thread::spawn(move || pipe.write_all(input.as_bytes()));
+    }
+    let output = child.wait_with_output().map_err(RuntimeError::Io)?;
+    // usize::MAX if the program was killed by a signal
+    let code = output
+        .status
+        .code()
+        .map_or(usize::MAX, |code| code as usize);
+    let stdout = state.alloc_str(&String::from_utf8_lossy(&output.stdout))?;
+    let stderr = state.alloc_str(&String::from_utf8_lossy(&output.stderr))?;
+    Ok(partial(
+        "process result",
+        field,
+        vec![
+            Value::Number(code),
+            Value::Number(stdout),
+            Value::Number(stderr),
+        ],
+    ))
+}
+
+/// Field 0 of a process result is the exit code, 1 the stdout and 2 the stderr
+fn field(
+    _: &mut InterpreterContext,
+    code: usize,
+    stdout: usize,
+    stderr: usize,
+    index: usize,
+) -> Result<usize, RuntimeError> {
+    match index {
+        0 => Ok(code),
+        1 => Ok(stdout),
+        2 => Ok(stderr),
+        _ => Err(RuntimeError::ExplicitlyRaisedMessage(
+            "process results only have the fields 0 (exit code), 1 (stdout) and 2 (stderr)",
+        )),
+    }
+}
+
+// the tests run `cat`, `true` and `false`
+#[cfg(all(test, unix))]
+mod test {
+    use crate::{
+        engine::{Engine, Error},
+        interpreter::{RuntimeError, Value},
+    };
+
+    fn engine() -> Engine {
+        let mut engine = Engine::new();
+        engine.interpreter_mut().permissions_mut().process = true;
+        engine
+    }
+
+    #[test]
+    fn spawn_reports_output_and_exit_code() {
+        let mut engine = engine();
+        engine
+            .eval("let { result; } (spawn (* (inspect { cat; })) 0 (* (inspect { hello; })) 0)")
+            .unwrap();
+        assert!(matches!(engine.eval("result 0"), Ok(Value::Number(0))));
+        assert!(matches!(
+            engine.eval("len (result 1)"),
+            Ok(Value::Number(5))
+        ));
+        assert!(matches!(
+            engine.eval("(run (* (inspect { false; })) 0) 0"),
+            Ok(Value::Number(1))
+        ));
+        assert!(matches!(
+            engine.eval("run (* (inspect { stayc-missing-program; })) 0"),
+            Err(Error::Runtime(RuntimeError::Io(_)))
+        ));
+    }
+
+    #[test]
+    fn processes_need_permission() {
+        let mut engine = Engine::new();
+        assert!(matches!(
+            engine.eval("run (* (inspect { true; })) 0"),
+            Err(Error::Runtime(RuntimeError::PermissionDenied(_)))
+        ));
+    }
+}
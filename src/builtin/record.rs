@@ -0,0 +1,126 @@
+use std::rc::Rc;
+
+use crate::{
+    ast_parser::ProgramAST,
+    interpreter::{
+        InterpreterContext, InterpreterFunctionDef, Record, RuntimeError, Value, ValueFunction,
+    },
+};
+
+pub fn register(interpreter: &mut InterpreterContext) {
+    interpreter.register_native_fn("record", record);
+    interpreter.register_native_fn("field", field);
+    interpreter.register_native_fn("update", update);
+    interpreter.register_native_fn("record?", is_record);
+}
+
+/// The tokens of a function like `{ x; y; }`
+fn tokens(func: &ValueFunction) -> Result<Vec<String>, RuntimeError> {
+    match &func.func {
+        InterpreterFunctionDef::FunctionDef { name: _, def } => def
+            .block
+            .iter()
+            .map(|statement| match statement {
+                ProgramAST::FunctionRef { token } => Ok(token.clone()),
+                _ => Err(RuntimeError::ExplicitlyRaisedMessage(
+                    "field names have to be a function of tokens",
+                )),
+            })
+            .collect(),
+        InterpreterFunctionDef::BuiltIn { .. } => Err(RuntimeError::ExplicitlyRaisedMessage(
+            "field names have to be a function of tokens",
+        )),
+    }
+}
+
+fn single_token(func: &ValueFunction) -> Result<String, RuntimeError> {
+    match tokens(func)?.as_slice() {
+        [token] => Ok(token.clone()),
+        _ => Err(RuntimeError::ExplicitlyRaisedMessage(
+            "field name has to be a function containing one token",
+        )),
+    }
+}
+
+/// `record { x; y; }` returns a constructor taking the values of the fields in order
+fn record(_: &mut InterpreterContext, fields: ValueFunction) -> Result<Value, RuntimeError> {
+    let names = tokens(&fields)?;
+    if names.is_empty() {
+        return Ok(Value::Record(Record::new(vec![])));
+    }
+    Ok(Value::Function(ValueFunction {
+        func: InterpreterFunctionDef::BuiltIn {
+            name: format!("record {{ {}; }}", names.join("; ")),
+            arg_count: names.len(),
+            func: Rc::new(move |_, values| {
+                Ok(Value::Record(Record::new(
+                    names.iter().cloned().zip(values).collect(),
+                )))
+            }),
+        },
+        bound_context: vec![],
+        bound_variables: vec![],
+    }))
+}
+
+/// `field r { x; }` is the same as `r.x`
+fn field(
+    _: &mut InterpreterContext,
+    record: Record,
+    name: ValueFunction,
+) -> Result<Value, RuntimeError> {
+    let name = single_token(&name)?;
+    record
+        .get(&name)
+        .cloned()
+        .ok_or(RuntimeError::NoSuchField(name))
+}
+
+/// Returns a copy of the record with one field changed
+fn update(
+    _: &mut InterpreterContext,
+    record: Record,
+    name: ValueFunction,
+    value: Value,
+) -> Result<Record, RuntimeError> {
+    let name = single_token(&name)?;
+    record
+        .with(&name, value)
+        .ok_or(RuntimeError::NoSuchField(name))
+}
+
+fn is_record(_: &mut InterpreterContext, value: Value) -> Result<bool, RuntimeError> {
+    Ok(matches!(value, Value::Record(_)))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        engine::{Engine, Error},
+        interpreter::{RuntimeError, Value},
+    };
+
+    #[test]
+    fn records_have_named_fields() {
+        let mut engine = Engine::new();
+        engine
+            .eval("{ let { point; } (record { x; y; }); let { p; } (point 1 2); }")
+            .unwrap();
+        assert!(matches!(engine.eval("p.y"), Ok(Value::Number(2))));
+        assert!(matches!(
+            engine.eval("field (update p { x; } 5) { x; }"),
+            Ok(Value::Number(5))
+        ));
+        assert!(matches!(engine.eval("p.x"), Ok(Value::Number(1))));
+        assert!(matches!(engine.eval("func? p"), Ok(Value::Number(0))));
+        assert!(matches!(engine.eval("unwrap p"), Ok(Value::Record(_))));
+        assert!(matches!(
+            engine.eval("update p { z; } 5"),
+            Err(Error::Runtime(RuntimeError::NoSuchField(_)))
+        ));
+        assert_eq!(
+            format!("{:?}", engine.eval("p").unwrap()),
+            "Record({x: Number(1), y: Number(2)})"
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use crate::interpreter::{InterpreterContext, RuntimeError};
+
+use super::{AllocationData, BuiltinState};
+
+pub fn register(interpreter: &mut InterpreterContext) {
+    interpreter.register_native("concat", concat);
+    interpreter.register_native("substr", substr);
+    interpreter.register_native("split", split);
+    interpreter.register_native("join", join);
+    interpreter.register_native("replace", replace);
+    interpreter.register_native("trim", trim);
+    interpreter.register_native("upper", upper);
+    interpreter.register_native("lower", lower);
+    interpreter.register_native("startswith", startswith);
+    interpreter.register_native("endswith", endswith);
+    interpreter.register_native("tostring", tostring);
+    interpreter.register_native("streq", streq);
+}
+
+fn concat(state: &mut BuiltinState, a: usize, b: usize) -> Result<usize, RuntimeError> {
+    let string = state.read_str(a)? + &state.read_str(b)?;
+    state.alloc_str(&string)
+}
+
+/// The `len` characters starting at character `start`, cut off at the end of the string
+fn substr(
+    state: &mut BuiltinState,
+    strptr: usize,
+    start: usize,
+    len: usize,
+) -> Result<usize, RuntimeError> {
+    let string: String = state
+        .read_str(strptr)?
+        .chars()
+        .skip(start)
+        .take(len)
+        .collect();
+    state.alloc_str(&string)
+}
+
+/// Returns an array of the parts of the string between occurrences of `separator`
+fn split(state: &mut BuiltinState, strptr: usize, separator: usize) -> Result<usize, RuntimeError> {
+    let string = state.read_str(strptr)?;
+    let separator = state.read_str(separator)?;
+    let parts: Vec<_> = if separator.is_empty() {
+        string.chars().map(String::from).collect()
+    } else {
+        string.split(&separator).map(str::to_owned).collect()
+    };
+    let parts = parts
+        .iter()
+        .map(|part| state.alloc_str(part))
+        .collect::<Result<_, _>>()?;
+    state.push_allocation(AllocationData::Cells(parts))
+}
+
+/// Joins an array of strings, putting `separator` between them
+fn join(state: &mut BuiltinState, array: usize, separator: usize) -> Result<usize, RuntimeError> {
+    let separator = state.read_str(separator)?;
+    let parts = match state.allocation(array).map(|a| &a.data) {
+        Some(AllocationData::Cells(cells)) => cells
+            .iter()
+            .map(|&cell| state.read_str(cell))
+            .collect::<Result<Vec<_>, _>>()?,
+        _ => {
+            return Err(RuntimeError::ExplicitlyRaisedMessage(
+                "expected an array of strings",
+            ))
+        }
+    };
+    state.alloc_str(&parts.join(&separator))
+}
+
+/// Character index of the first occurrence of `needle`, or `- 0 1` if there is
+/// none. Registered as `find` by the list builtins.
+pub(super) fn find(
+    state: &mut BuiltinState,
+    strptr: usize,
+    needle: usize,
+) -> Result<usize, RuntimeError> {
+    let string = state.read_str(strptr)?;
+    let needle = state.read_str(needle)?;
+    Ok(match string.find(&needle) {
+        Some(byte_index) => string[..byte_index].chars().count(),
+        None => usize::MAX,
+    })
+}
+
+/// Replaces all occurrences of `from` with `to`
+fn replace(
+    state: &mut BuiltinState,
+    strptr: usize,
+    from: usize,
+    to: usize,
+) -> Result<usize, RuntimeError> {
+    let string = state.read_str(strptr)?;
+    let from = state.read_str(from)?;
+    let to = state.read_str(to)?;
+    let replaced = if from.is_empty() {
+        string
+    } else {
+        string.replace(&from, &to)
+    };
+    state.alloc_str(&replaced)
+}
+
+fn trim(state: &mut BuiltinState, strptr: usize) -> Result<usize, RuntimeError> {
+    let string = state.read_str(strptr)?;
+    state.alloc_str(string.trim())
+}
+
+fn upper(state: &mut BuiltinState, strptr: usize) -> Result<usize, RuntimeError> {
+    let string = state.read_str(strptr)?.to_uppercase();
+    state.alloc_str(&string)
+}
+
+fn lower(state: &mut BuiltinState, strptr: usize) -> Result<usize, RuntimeError> {
+    let string = state.read_str(strptr)?.to_lowercase();
+    state.alloc_str(&string)
+}
+
+fn startswith(
+    state: &mut BuiltinState,
+    strptr: usize,
+    prefix: usize,
+) -> Result<bool, RuntimeError> {
+    Ok(state
+        .read_str(strptr)?
+        .starts_with(&state.read_str(prefix)?))
+}
+
+fn endswith(state: &mut BuiltinState, strptr: usize, suffix: usize) -> Result<bool, RuntimeError> {
+    Ok(state.read_str(strptr)?.ends_with(&state.read_str(suffix)?))
+}
+
+/// Formats a number in decimal
+fn tostring(state: &mut BuiltinState, number: usize) -> Result<usize, RuntimeError> {
+    state.alloc_str(&number.to_string())
+}
+
+/// Compares the text of two strings, no matter whether they are byte buffers or decoded
+fn streq(state: &mut BuiltinState, a: usize, b: usize) -> Result<bool, RuntimeError> {
+    Ok(state.read_str(a)? == state.read_str(b)?)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{builtin::BuiltinState, engine::Engine, interpreter::Value};
+
+    fn eval_str(engine: &mut Engine, source: &str) -> String {
+        match engine.eval(source) {
+            Ok(Value::Number(strptr)) => engine
+                .interpreter_mut()
+                .state_or_default::<BuiltinState>()
+                .read_str(strptr)
+                .unwrap(),
+            other => panic!("expected a string, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn string_builtins() {
+        let mut engine = Engine::new();
+        let state = engine.interpreter_mut().state_or_default::<BuiltinState>();
+        let text = state.alloc_str(" a,b,c ").unwrap();
+        let comma = state.alloc_str(",").unwrap();
+        let dash = state.alloc_str("-").unwrap();
+        engine.define("text", Value::Number(text));
+        engine.define("comma", Value::Number(comma));
+        engine.define("dash", Value::Number(dash));
+
+        assert_eq!(
+            eval_str(&mut engine, "join (split (trim text) comma) dash"),
+            "a-b-c"
+        );
+        assert_eq!(
+            eval_str(&mut engine, "upper (replace text comma dash)"),
+            " A-B-C "
+        );
+        assert_eq!(eval_str(&mut engine, "substr text 1 3"), "a,b");
+        assert_eq!(eval_str(&mut engine, "concat (tostring 42) dash"), "42-");
+        assert!(matches!(
+            engine.eval("find text comma"),
+            Ok(Value::Number(2))
+        ));
+        assert!(matches!(
+            engine.eval("find text dash"),
+            Ok(Value::Number(usize::MAX))
+        ));
+        assert!(matches!(
+            engine.eval("streq (decode comma) comma"),
+            Ok(Value::Number(1))
+        ));
+    }
+}
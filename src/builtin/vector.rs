@@ -0,0 +1,170 @@
+use crate::interpreter::{InterpreterContext, RuntimeError};
+
+use super::{AllocationData, BuiltinState};
+
+/// Space reserved by `vnew`, so that a new vector never shares its address
+const INITIAL_CAPACITY: usize = 4;
+
+pub fn register(interpreter: &mut InterpreterContext) {
+    interpreter.register_native("vnew", vnew);
+    interpreter.register_native("vpush", vpush);
+    interpreter.register_native("vpop", vpop);
+    interpreter.register_native("vget", vget);
+    interpreter.register_native("vset", vset);
+    interpreter.register_native("vlen", vlen);
+    interpreter.register_native("vslice", vslice);
+}
+
+impl BuiltinState {
+    /// Index of the cell allocation starting at `addr`
+    fn vector_index(&self, addr: usize) -> Result<usize, RuntimeError> {
+        // zero length allocations share their address with the next one
+        let first = self.heap.partition_point(|a| a.start_addr < addr);
+        self.heap[first..]
+            .iter()
+            .take_while(|a| a.start_addr == addr)
+            .position(|a| matches!(a.data, AllocationData::Cells(_)))
+            .map(|offset| first + offset)
+            .ok_or(RuntimeError::ExplicitlyRaisedMessage("not a vector"))
+    }
+
+    fn vector(&self, addr: usize) -> Result<&Vec<usize>, RuntimeError> {
+        match &self.heap[self.vector_index(addr)?].data {
+            AllocationData::Cells(cells) => Ok(cells),
+            _ => unreachable!(),
+        }
+    }
+
+    fn vector_mut(&mut self, addr: usize) -> Result<&mut Vec<usize>, RuntimeError> {
+        let index = self.vector_index(addr)?;
+        match &mut self.heap[index].data {
+            AllocationData::Cells(cells) => Ok(cells),
+            _ => unreachable!(),
+        }
+    }
+
+    fn push_vector(&mut self, cells: Vec<usize>) -> Result<usize, RuntimeError> {
+        let reserved = cells.len().max(INITIAL_CAPACITY);
+        let addr = self.push_allocation(AllocationData::Cells(cells))?;
+        self.heap.last_mut().unwrap().reserved = reserved;
+        Ok(addr)
+    }
+}
+
+fn vnew(state: &mut BuiltinState) -> Result<usize, RuntimeError> {
+    state.push_vector(vec![])
+}
+
+/// Appends `value` and returns the address of the vector. A vector that is out
+/// of reserved space moves to the end of the heap with twice the space, so the
+/// old address must not be used anymore.
+fn vpush(state: &mut BuiltinState, addr: usize, value: usize) -> Result<usize, RuntimeError> {
+    let index = state.vector_index(addr)?;
+    let is_last = index + 1 == state.heap.len();
+    let allocation = &mut state.heap[index];
+    if allocation.data.len() < allocation.reserved || is_last || state.strict {
+        let len = allocation.data.len();
+        state.check_slot_size(len + 1)?;
+        let allocation = &mut state.heap[index];
+        allocation.reserved = allocation.reserved.max(len + 1);
+        state.vector_mut(addr)?.push(value);
+        return Ok(addr);
+    }
+    // an empty byte buffer stays behind, so the old address is no vector
+    // anymore without shifting the rest of the heap, until `gc` reclaims it
+    allocation.reserved = 0;
+    let mut cells = match std::mem::replace(&mut allocation.data, AllocationData::Bytes(vec![])) {
+        AllocationData::Cells(cells) => cells,
+        _ => unreachable!(),
+    };
+    let reserved = (cells.len() * 2).max(INITIAL_CAPACITY);
+    cells.push(value);
+    let new_addr = state.push_allocation(AllocationData::Cells(cells))?;
+    state.heap.last_mut().unwrap().reserved = reserved;
+    Ok(new_addr)
+}
+
+/// Removes and returns the last element
+fn vpop(state: &mut BuiltinState, addr: usize) -> Result<usize, RuntimeError> {
+    state
+        .vector_mut(addr)?
+        .pop()
+        .ok_or(RuntimeError::ExplicitlyRaisedMessage(
+            "pop from an empty vector",
+        ))
+}
+
+fn vget(state: &mut BuiltinState, addr: usize, index: usize) -> Result<usize, RuntimeError> {
+    let cells = state.vector(addr)?;
+    cells.get(index).copied().ok_or(RuntimeError::OutOfBounds {
+        base: addr,
+        offset: index,
+        len: cells.len(),
+    })
+}
+
+fn vset(
+    state: &mut BuiltinState,
+    addr: usize,
+    index: usize,
+    value: usize,
+) -> Result<usize, RuntimeError> {
+    let cells = state.vector_mut(addr)?;
+    let len = cells.len();
+    match cells.get_mut(index) {
+        Some(cell) => {
+            *cell = value;
+            Ok(1)
+        }
+        None => Err(RuntimeError::OutOfBounds {
+            base: addr,
+            offset: index,
+            len,
+        }),
+    }
+}
+
+fn vlen(state: &mut BuiltinState, addr: usize) -> Result<usize, RuntimeError> {
+    Ok(state.vector(addr)?.len())
+}
+
+/// Copies the elements from `start` up to (excluding) `end` into a new vector
+fn vslice(
+    state: &mut BuiltinState,
+    addr: usize,
+    start: usize,
+    end: usize,
+) -> Result<usize, RuntimeError> {
+    let cells = state.vector(addr)?;
+    let end = end.min(cells.len());
+    let slice = cells[start.min(end)..end].to_vec();
+    state.push_vector(slice)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{vget, vnew, vpop, vpush, vslice};
+    use crate::builtin::BuiltinState;
+
+    #[test]
+    fn vectors_grow_in_place_until_full() {
+        let mut state = BuiltinState::default();
+        let vector = vnew(&mut state).unwrap();
+        let other = state.alloc(1).unwrap();
+        let mut addr = vector;
+        for i in 0..4 {
+            addr = vpush(&mut state, addr, i).unwrap();
+        }
+        assert_eq!(addr, vector);
+        assert!(other >= vector + 4);
+        addr = vpush(&mut state, addr, 4).unwrap();
+        assert!(addr > other);
+        assert!(state.get(vector).is_err());
+        assert!(vpush(&mut state, vector, 5).is_err());
+        assert_eq!(state.get(addr + 4).unwrap(), 4);
+        assert_eq!(vpop(&mut state, addr).unwrap(), 4);
+        let slice = vslice(&mut state, addr, 1, 10).unwrap();
+        assert_eq!(vget(&mut state, slice, 2).unwrap(), 3);
+        assert!(vget(&mut state, slice, 3).is_err());
+    }
+}
@@ -0,0 +1,254 @@
+use std::{fmt::Display, path::Path};
+
+use crate::{
+    ast_parser::{ParsingError, ProgramAST},
+    builtin::{
+        create_builtin_interpreter,
+        module::{enter_file, leave_file},
+    },
+    interpreter::{FunctionContext, InterpreterContext, RuntimeError, Value},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Parsing(ParsingError),
+    Runtime(RuntimeError),
+    Io(std::io::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Parsing(ParsingError::Syntax(error)) => {
+                f.write_fmt(format_args!("syntax error: {}", error))
+            }
+            Error::Parsing(error) => f.write_fmt(format_args!("parsing error: {:?}", error)),
+            Error::Runtime(error) => f.write_fmt(format_args!("runtime error: {:?}", error)),
+            Error::Io(error) => f.write_fmt(format_args!("io error: {}", error)),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// High level entry point for embedding stayc. Top level `let` bindings of
+/// evaluated code persist as globals between calls.
+pub struct Engine {
+    interpreter: InterpreterContext,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self::with_interpreter(create_builtin_interpreter())
+    }
+
+    pub fn with_interpreter(mut interpreter: InterpreterContext) -> Self {
+        if interpreter.function_context.is_empty() {
+            interpreter
+                .function_context
+                .push_back(FunctionContext(vec![]));
+        }
+        Self { interpreter }
+    }
+
+    pub fn interpreter(&self) -> &InterpreterContext {
+        &self.interpreter
+    }
+
+    pub fn interpreter_mut(&mut self) -> &mut InterpreterContext {
+        &mut self.interpreter
+    }
+
+    /// Parses a program. Source that isn't a code block is treated as the
+    /// statements of one, like in the REPL (eg. `+ 1 2` is `{ + 1 2; }`).
+    pub fn parse(source: &str) -> Result<ProgramAST, Error> {
+        let source = source.trim();
+        let ast = if source.starts_with('{') {
+            ProgramAST::parse_source(source)
+        } else {
+            ProgramAST::parse_source(&format!("{{{};}}", source.trim_end_matches(';')))
+        };
+        ast.map_err(Error::Parsing)
+    }
+
+    pub fn eval(&mut self, source: &str) -> Result<Value, Error> {
+        let ast = Self::parse(source)?;
+        let depth = self.interpreter.function_context.len();
+        let result = self.interpreter.run_anonym_func(ast, vec![], false);
+        // keep the top level bindings of the program as globals
+        self.unwind_to(depth + 1);
+        if self.interpreter.function_context.len() > depth {
+            let FunctionContext(mut bindings) =
+                self.interpreter.function_context.pop_back().unwrap();
+            self.globals().append(&mut bindings);
+        }
+        result.map_err(Error::Runtime)
+    }
+
+    /// Evaluates a file. Imports in it are resolved relative to its directory.
+    pub fn eval_file(&mut self, path: impl AsRef<Path>) -> Result<Value, Error> {
+        let path = path.as_ref().canonicalize().map_err(Error::Io)?;
+        let source = std::fs::read_to_string(&path).map_err(Error::Io)?;
+        enter_file(&mut self.interpreter, path);
+        let result = self.eval(&source);
+        leave_file(&mut self.interpreter);
+        result
+    }
+
+    /// Defines a global, shadowing any previous definition with the same name
+    pub fn define(&mut self, name: &str, value: Value) {
+        self.globals().push((name.to_owned(), value));
+    }
+
+    /// Looks up a global or builtin
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.interpreter.resolve(name)
+    }
+
+    pub fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value, Error> {
+        let func = match self.get_global(name) {
+            Some(Value::Function(func)) => func,
+            Some(Value::Number(n)) => {
+                return Err(Error::Runtime(RuntimeError::ValueNotAFunction(n)))
+            }
+            Some(Value::Record(record)) => {
+                return Err(Error::Runtime(RuntimeError::RecordNotAFunction(record)))
+            }
+            None => {
+                return Err(Error::Runtime(RuntimeError::UndefinedFunctionReference(
+                    name.to_owned(),
+                )))
+            }
+        };
+        let depth = self.interpreter.function_context.len();
+        let result = self.interpreter.apply(func, args);
+        self.unwind_to(depth);
+        result.map_err(Error::Runtime)
+    }
+
+    /// Drops the scopes a failed evaluation left behind
+    fn unwind_to(&mut self, depth: usize) {
+        while self.interpreter.function_context.len() > depth {
+            self.interpreter.function_context.pop_back();
+        }
+    }
+
+    fn globals(&mut self) -> &mut Vec<(String, Value)> {
+        &mut self.interpreter.function_context.front_mut().unwrap().0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::Cell, rc::Rc};
+
+    use super::{Engine, Error};
+    use crate::interpreter::{RuntimeError, Value};
+
+    fn number(value: Result<Value, Error>) -> usize {
+        match value {
+            Ok(Value::Number(n)) => n,
+            other => panic!("expected a number, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn eval_keeps_globals() {
+        let mut engine = Engine::new();
+        number(engine.eval("let { a; } 4"));
+        assert_eq!(number(engine.eval("+ a 3;")), 7);
+        assert!(matches!(engine.get_global("a"), Some(Value::Number(4))));
+    }
+
+    #[test]
+    fn syntax_errors_report_their_position() {
+        let mut engine = Engine::new();
+        let error = engine.eval("{ + 1 2 }").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "syntax error: missing ';' before '}' at token 4"
+        );
+    }
+
+    #[test]
+    fn prelude_is_loaded() {
+        let mut engine = Engine::new();
+        assert!(matches!(engine.eval("eq 2 2"), Ok(Value::Number(1))));
+        assert!(matches!(
+            engine.eval("len (map ([ 1 2 3 ]) { x => + x 1; })"),
+            Ok(Value::Number(3))
+        ));
+    }
+
+    #[test]
+    fn define_and_call() {
+        let mut engine = Engine::new();
+        engine.define("x", Value::Number(2));
+        engine.eval("{ let { add; } { a b => + a b; }; }").unwrap();
+        assert_eq!(
+            number(engine.call("add", vec![Value::Number(1), Value::Number(2)])),
+            3
+        );
+        assert_eq!(number(engine.eval("add x 5")), 7);
+        assert!(matches!(
+            engine.call("add", vec![Value::Number(1)]),
+            Ok(Value::Function(_))
+        ));
+    }
+
+    #[test]
+    fn errors_do_not_leak_scopes() {
+        let mut engine = Engine::new();
+        assert!(matches!(engine.eval("{ + 1 }"), Err(Error::Parsing(_))));
+        assert!(matches!(
+            engine.eval("undefined 1"),
+            Err(Error::Runtime(RuntimeError::UndefinedFunctionReference(_)))
+        ));
+        assert_eq!(engine.interpreter().function_context.len(), 1);
+    }
+
+    #[test]
+    fn builtins_capture_host_state() {
+        let mut engine = Engine::new();
+        let counter = Rc::new(Cell::new(0));
+        let captured = counter.clone();
+        engine
+            .interpreter_mut()
+            .register_builtin("count".to_owned(), 1, move |_, args| {
+                captured.set(captured.get() + 1);
+                Ok(args[0].clone())
+            });
+        engine.eval("{ count 1; count 2; }").unwrap();
+        assert_eq!(counter.get(), 2);
+    }
+
+    #[test]
+    fn host_state_coexists_with_heap() {
+        #[derive(Default)]
+        struct Calls(usize);
+
+        fn record(calls: &mut Calls, value: usize) -> Result<usize, RuntimeError> {
+            calls.0 += 1;
+            Ok(value)
+        }
+
+        let mut engine = Engine::new();
+        engine.interpreter_mut().register_native("record", record);
+        engine.eval("{ record (alloc 2); record 1; }").unwrap();
+        assert_eq!(engine.interpreter().state::<Calls>().unwrap().0, 2);
+        assert_eq!(engine.interpreter().heap_stats().unwrap().allocations, 1);
+    }
+}
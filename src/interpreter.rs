@@ -1,12 +1,28 @@
-use crate::ast_parser::{FunctionDef, ProgramAST};
-use std::{collections::LinkedList, fmt::Debug, rc::Rc};
+use crate::{
+    ast_parser::{FunctionDef, ParsingError, ProgramAST},
+    io::{Io, StdIo},
+    machine::Signal,
+    permissions::{Permission, Permissions},
+};
+use std::{
+    any::{Any, TypeId},
+    collections::{HashMap, LinkedList},
+    fmt::Debug,
+    path::PathBuf,
+    rc::Rc,
+};
+
+/// A native function. Builtins can capture host state, which is shared between
+/// all values referring to the builtin.
+pub type BuiltinFunction =
+    Rc<dyn Fn(&mut InterpreterContext, Vec<Value>) -> Result<Value, RuntimeError>>;
 
 #[derive(Clone)]
 pub enum InterpreterFunctionDef {
     BuiltIn {
         name: String,
         arg_count: usize,
-        func: fn(&mut InterpreterContext, Vec<Value>) -> Result<Value, RuntimeError>,
+        func: BuiltinFunction,
     },
     FunctionDef {
         name: String,
@@ -29,25 +45,6 @@ impl Debug for InterpreterFunctionDef {
     }
 }
 
-impl FunctionDef {
-    fn get_ith_arg_name(&self, index: usize) -> Option<String> {
-        self.arg_tokens.get(index).map(|e| e.clone())
-    }
-}
-
-impl InterpreterFunctionDef {
-    fn get_ith_arg_name(&self, index: usize) -> Option<String> {
-        match self {
-            InterpreterFunctionDef::BuiltIn {
-                name: _,
-                arg_count: _,
-                func: _,
-            } => None,
-            InterpreterFunctionDef::FunctionDef { name: _, def } => def.get_ith_arg_name(index),
-        }
-    }
-}
-
 #[derive(Debug, Clone)]
 pub struct ValueFunction {
     pub func: InterpreterFunctionDef,
@@ -55,27 +52,115 @@ pub struct ValueFunction {
     pub bound_variables: Vec<Value>,
 }
 
+/// Named fields, immutable once created. Cloning a record is cheap.
+#[derive(Clone)]
+pub struct Record(Rc<Vec<(String, Value)>>);
+
+impl Record {
+    pub fn new(fields: Vec<(String, Value)>) -> Self {
+        Self(Rc::new(fields))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.0
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value)
+    }
+
+    /// A copy of the record with the field `name` set to `value`, or `None` if there is no such field
+    pub fn with(&self, name: &str, value: Value) -> Option<Record> {
+        let index = self.0.iter().position(|(field, _)| field == name)?;
+        let mut record = self.clone();
+        Rc::make_mut(&mut record.0)[index].1 = value;
+        Some(record)
+    }
+
+    pub fn fields(&self) -> impl Iterator<Item = &(String, Value)> {
+        self.0.iter()
+    }
+}
+
+impl Debug for Record {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut map = f.debug_map();
+        for (name, value) in self.0.iter() {
+            map.entry(&format_args!("{}", name), value);
+        }
+        map.finish()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Value {
     Number(usize),
     Function(ValueFunction),
+    Record(Record),
 }
 
+impl Value {
+    /// Calls `visit` for every number contained in this value, descending into
+    /// the bound context and bound variables of function values and the fields
+    /// of records.
+    pub fn visit_numbers(&self, visit: &mut impl FnMut(usize)) {
+        match self {
+            Value::Number(n) => visit(*n),
+            Value::Function(func) => {
+                for (_, value) in &func.bound_context {
+                    value.visit_numbers(visit);
+                }
+                for value in &func.bound_variables {
+                    value.visit_numbers(visit);
+                }
+            }
+            Value::Record(record) => {
+                for (_, value) in record.fields() {
+                    value.visit_numbers(visit);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct FunctionContext(pub Vec<(String, Value)>);
 
 pub struct InterpreterContext {
     builtins: Vec<InterpreterFunctionDef>,
     pub function_context: LinkedList<FunctionContext>,
-    pub state: Box<dyn std::any::Any>,
+    /// Extension state of builtins and the host, one value per type
+    state: HashMap<TypeId, Box<dyn Any>>,
+    io: Box<dyn Io>,
+    permissions: Permissions,
 }
 
 #[derive(Debug)]
 pub enum RuntimeError {
     UndefinedFunctionReference(String),
     ValueNotAFunction(usize),
+    RecordNotAFunction(Record),
+    NoSuchField(String),
     EmptyFunction,
     ExplicitlyRaised,
     ExplicitlyRaisedMessage(&'static str),
+    /// A module imported itself, directly or through other modules
+    ImportCycle(PathBuf),
+    ImportParsing(PathBuf, ParsingError),
+    OutOfBounds {
+        base: usize,
+        offset: usize,
+        len: usize,
+    },
+    WrongArgumentType {
+        builtin: String,
+        index: usize,
+        expected: &'static str,
+        found: Box<Value>,
+    },
+    Io(std::io::Error),
+    PermissionDenied(Permission),
+    /// A request of a builtin to the evaluator, see `Signal`
+    Signal(Box<Signal>),
 }
 
 impl InterpreterContext {
@@ -83,22 +168,70 @@ impl InterpreterContext {
         Self {
             builtins: vec![],
             function_context: LinkedList::new(),
-            state: Box::new(()),
+            state: HashMap::new(),
+            io: Box::new(StdIo),
+            permissions: Permissions::default(),
         }
     }
 
+    pub fn permissions(&self) -> &Permissions {
+        &self.permissions
+    }
+
+    pub fn permissions_mut(&mut self) -> &mut Permissions {
+        &mut self.permissions
+    }
+
+    /// Replaces the streams used by builtins, which default to the process' own
+    pub fn set_io(&mut self, io: impl Io + 'static) {
+        self.io = Box::new(io);
+    }
+
+    pub fn io(&mut self) -> &mut dyn Io {
+        self.io.as_mut()
+    }
+
+    /// Stores `value` as the state of type `T`, returning the previous one
+    pub fn insert_state<T: 'static>(&mut self, value: T) -> Option<T> {
+        self.state
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|old| old.downcast().ok())
+            .map(|old| *old)
+    }
+
+    pub fn state<T: 'static>(&self) -> Option<&T> {
+        self.state
+            .get(&TypeId::of::<T>())
+            .and_then(|state| state.downcast_ref())
+    }
+
+    pub fn state_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.state
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|state| state.downcast_mut())
+    }
+
+    /// Like `state_mut`, but inserts `T::default()` if there is no state of type `T` yet
+    pub fn state_or_default<T: Default + 'static>(&mut self) -> &mut T {
+        self.state
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(T::default()))
+            .downcast_mut()
+            .expect("state is stored under its own type id")
+    }
+
     pub fn register_builtin(
         &mut self,
         name: String,
         arg_count: usize,
-        func: fn(&mut InterpreterContext, Vec<Value>) -> Result<Value, RuntimeError>,
+        func: impl Fn(&mut InterpreterContext, Vec<Value>) -> Result<Value, RuntimeError> + 'static,
     ) {
         self.builtins.insert(
             0,
             InterpreterFunctionDef::BuiltIn {
                 name,
                 arg_count,
-                func,
+                func: Rc::new(func),
             },
         )
     }
@@ -114,16 +247,7 @@ impl InterpreterContext {
         args: Vec<(String, Value)>,
         should_destroy_context: bool,
     ) -> Result<Value, RuntimeError> {
-        let mut last_value = None;
-        self.function_context
-            .push_back(FunctionContext(args.clone()));
-        for s in func.block {
-            last_value = Some(self.run(s)?);
-        }
-        if should_destroy_context {
-            self.function_context.pop_back();
-        }
-        last_value.ok_or(RuntimeError::EmptyFunction)
+        self.eval_block(func.block, args, should_destroy_context)
     }
 
     pub fn run_anonym_func(
@@ -141,107 +265,91 @@ impl InterpreterContext {
     }
 
     pub fn run_func_value(
+        &mut self,
+        func: ValueFunction,
+        args: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        self.eval_call(func, args)
+    }
+
+    /// Applies `args` to a function value like call expressions do, returning
+    /// a partially applied function if not all parameters are bound yet
+    pub fn apply(
         &mut self,
         mut func: ValueFunction,
         mut args: Vec<Value>,
     ) -> Result<Value, RuntimeError> {
-        func.bound_variables.append(&mut args);
-        match &func.func {
+        let arg_count = match &func.func {
             InterpreterFunctionDef::BuiltIn {
-                name,
+                name: _,
                 arg_count,
-                func: builtin_func,
-            } => builtin_func(self, func.bound_variables),
-            InterpreterFunctionDef::FunctionDef { name, def } => self.run_func(
-                def.clone(),
-                {
-                    let mut vars = func
-                        .bound_variables
-                        .iter()
-                        .enumerate()
-                        .map(|(i, e)| {
-                            (
-                                func.func
-                                    .get_ith_arg_name(i)
-                                    .unwrap_or("".to_owned())
-                                    .clone(),
-                                e.clone(),
-                            )
-                        })
-                        .collect::<Vec<(String, Value)>>();
-                    vars.append(&mut func.bound_context);
-                    vars
-                },
-                true,
-            ),
+                func: _,
+            } => *arg_count,
+            InterpreterFunctionDef::FunctionDef { name: _, def } => def.arg_tokens.len(),
+        };
+        if func.bound_variables.len() + args.len() < arg_count {
+            func.bound_variables.append(&mut args);
+            return Ok(Value::Function(func));
         }
+        self.run_func_value(func, args)
     }
 
     pub fn run(&mut self, program: ProgramAST) -> Result<Value, RuntimeError> {
-        match program {
-            ProgramAST::FunctionCall { function, arg } => {
-                let function_to_run = self.run(*function)?;
-                match function_to_run {
-                    Value::Number(n) => Err(RuntimeError::ValueNotAFunction(n)),
-                    Value::Function(mut value_function) => {
-                        match value_function.func.clone() {
-                            InterpreterFunctionDef::FunctionDef { name: _, def: func } => {
-                                value_function.bound_variables.push(self.run(*arg)?);
-                                if value_function.bound_variables.len() >= func.arg_tokens.len() {
-                                    // don't pass arguments since values are already bound
-                                    self.run_func_value(value_function, vec![])
-                                } else {
-                                    Ok(Value::Function(value_function))
-                                }
-                            }
-                            InterpreterFunctionDef::BuiltIn {
-                                name: _,
-                                arg_count,
-                                func,
-                            } => {
-                                value_function.bound_variables.push(self.run(*arg)?);
-                                if value_function.bound_variables.len() >= arg_count {
-                                    func(self, value_function.bound_variables)
-                                } else {
-                                    Ok(Value::Function(value_function))
-                                }
-                            }
-                        }
-                    }
+        self.eval_program(program)
+    }
+
+    /// Resolves a token to a variable in scope or a registered function
+    pub fn resolve(&self, token: &str) -> Option<Value> {
+        if let Some(value) = self.lookup(&token.to_owned()) {
+            return Some(value);
+        }
+        // `namespace.name` looks up a member of an imported module or a record field
+        if let Some((namespace, member)) = token.rsplit_once('.') {
+            match self.resolve(namespace) {
+                Some(Value::Function(namespace)) => {
+                    return namespace
+                        .bound_context
+                        .into_iter()
+                        .rev()
+                        .find(|(name, _)| name == member)
+                        .map(|(_, value)| value)
                 }
+                Some(Value::Record(record)) => return record.get(member).cloned(),
+                _ => {}
             }
-            ProgramAST::FunctionDef(func_def) => Ok(Value::Function(ValueFunction {
-                bound_context: vec![],
-                func: InterpreterFunctionDef::FunctionDef {
-                    name: "anonymous".to_owned(),
-                    def: func_def,
-                },
-                bound_variables: vec![],
-            })),
-            ProgramAST::FunctionRef { token } => {
-                match self.lookup(&token) {
-                    Some(s) => return Ok(s),
-                    None => {}
-                };
-                if let Some(value) = self.builtins.iter().find(|f| match f {
-                    InterpreterFunctionDef::BuiltIn {
-                        name,
-                        arg_count: _,
-                        func: _,
-                    } => *name == token,
-                    InterpreterFunctionDef::FunctionDef { name, def: _ } => *name == token,
-                }) {
-                    return Ok(Value::Function(ValueFunction {
-                        bound_context: vec![],
-                        func: value.clone(),
-                        bound_variables: vec![],
-                    }));
-                }
-                Err(RuntimeError::UndefinedFunctionReference(token))
+        }
+        self.builtins
+            .iter()
+            .find(|f| match f {
+                InterpreterFunctionDef::BuiltIn {
+                    name,
+                    arg_count: _,
+                    func: _,
+                } => *name == token,
+                InterpreterFunctionDef::FunctionDef { name, def: _ } => *name == token,
+            })
+            .map(|value| {
+                Value::Function(ValueFunction {
+                    bound_context: vec![],
+                    func: value.clone(),
+                    bound_variables: vec![],
+                })
+            })
+    }
+
+    /// Every number reachable from a live scope. Any of them may be a pointer,
+    /// so they act as the (conservative) roots for garbage collection.
+    pub fn root_numbers(&self) -> Vec<usize> {
+        let mut roots = Vec::new();
+        for context in self.function_context.iter() {
+            for (_, value) in context.0.iter() {
+                value.visit_numbers(&mut |n| roots.push(n));
             }
-            ProgramAST::Value { value } => Ok(Value::Number(value)),
         }
+        self.visit_running(&mut |n| roots.push(n));
+        roots
     }
+
     pub fn lookup(&self, token: &String) -> Option<Value> {
         let mut found_value = None;
         for context in self.function_context.iter() {
@@ -0,0 +1,117 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fmt::Display,
+    io::{BufRead, Write},
+    rc::Rc,
+};
+
+use crate::interpreter::{InterpreterContext, RuntimeError};
+
+/// The standard streams used by builtins like `print` and `read`
+pub trait Io {
+    fn write_stdout(&mut self, text: &str) -> std::io::Result<()>;
+    fn write_stderr(&mut self, text: &str) -> std::io::Result<()>;
+    /// Reads one line without its line ending, or `None` at the end of input
+    fn read_line(&mut self) -> std::io::Result<Option<String>>;
+}
+
+/// The streams of the process
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StdIo;
+
+impl Io for StdIo {
+    fn write_stdout(&mut self, text: &str) -> std::io::Result<()> {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(text.as_bytes())?;
+        stdout.flush()
+    }
+
+    fn write_stderr(&mut self, text: &str) -> std::io::Result<()> {
+        std::io::stderr().write_all(text.as_bytes())
+    }
+
+    fn read_line(&mut self) -> std::io::Result<Option<String>> {
+        std::io::stdin().lock().lines().next().transpose()
+    }
+}
+
+#[derive(Debug, Default)]
+struct MemoryBuffers {
+    stdout: String,
+    stderr: String,
+    stdin: VecDeque<String>,
+}
+
+/// In-memory streams, for tests and the wasm playground. Clones share their
+/// buffers, so the host keeps a clone to feed input and collect output.
+#[derive(Debug, Default, Clone)]
+pub struct MemoryIo(Rc<RefCell<MemoryBuffers>>);
+
+impl MemoryIo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues lines to be returned by `read`
+    pub fn push_input(&self, input: &str) {
+        self.0
+            .borrow_mut()
+            .stdin
+            .extend(input.lines().map(str::to_owned));
+    }
+
+    /// Returns and clears everything written to stdout
+    pub fn take_stdout(&self) -> String {
+        std::mem::take(&mut self.0.borrow_mut().stdout)
+    }
+
+    /// Returns and clears everything written to stderr
+    pub fn take_stderr(&self) -> String {
+        std::mem::take(&mut self.0.borrow_mut().stderr)
+    }
+}
+
+impl Io for MemoryIo {
+    fn write_stdout(&mut self, text: &str) -> std::io::Result<()> {
+        self.0.borrow_mut().stdout += text;
+        Ok(())
+    }
+
+    fn write_stderr(&mut self, text: &str) -> std::io::Result<()> {
+        self.0.borrow_mut().stderr += text;
+        Ok(())
+    }
+
+    fn read_line(&mut self) -> std::io::Result<Option<String>> {
+        Ok(self.0.borrow_mut().stdin.pop_front())
+    }
+}
+
+impl InterpreterContext {
+    /// Writes a line to the interpreter's stdout
+    pub fn println(&mut self, line: impl Display) -> Result<(), RuntimeError> {
+        self.io()
+            .write_stdout(&format!("{}\n", line))
+            .map_err(RuntimeError::Io)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MemoryIo;
+    use crate::{engine::Engine, interpreter::Value};
+
+    #[test]
+    fn builtins_use_memory_io() {
+        let io = MemoryIo::new();
+        let mut engine = Engine::new();
+        engine.interpreter_mut().set_io(io.clone());
+        engine.interpreter_mut().permissions_mut().stdin = true;
+        io.push_input("hello");
+        engine.eval("{ print 4; printstr (read 0); }").unwrap();
+        assert_eq!(io.take_stdout(), "Number(4)\nhello\n");
+        // reading past the end of input returns a null pointer
+        assert!(matches!(engine.eval("read 0"), Ok(Value::Number(0))));
+    }
+}
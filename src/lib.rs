@@ -1,100 +1,89 @@
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::wasm_bindgen;
 
 pub mod ast_parser;
 pub mod builtin;
+pub mod engine;
 pub mod interpreter;
+pub mod io;
+pub mod machine;
+pub mod native;
+pub mod permissions;
+pub mod prelude;
 pub mod str_ext;
 pub mod tokenizer;
 
 #[cfg(feature = "wasm")]
-static mut INTERPRETER: Option<interpreter::InterpreterContext> = None;
-#[cfg(feature = "wasm")]
-static mut STDOUT: String = String::new();
+struct Playground {
+    engine: engine::Engine,
+    io: io::MemoryIo,
+}
 
 #[cfg(feature = "wasm")]
-fn get_interpreter() -> &'static mut interpreter::InterpreterContext {
-    use builtin::register_builtins;
-
-    use crate::interpreter::InterpreterContext;
+impl Playground {
+    fn new() -> Self {
+        use crate::{builtin::create_builtin_interpreter, engine::Engine, io::MemoryIo};
 
-    if unsafe { INTERPRETER.is_none() } {
-        unsafe {
-            INTERPRETER = Some(InterpreterContext::new());
-            register_builtins(&mut INTERPRETER.as_mut().unwrap());
-            INTERPRETER
-                .as_mut()
-                .unwrap()
-                .register_builtin("print".to_owned(), 1, |_, mut args| {
-                    STDOUT += &format!("{:?}\n", args[0]);
-                    Ok(args.swap_remove(0))
-                });
-            INTERPRETER
-                .as_mut()
-                .unwrap()
-                .register_builtin("helppredef".to_owned(), 0, |_, _| {
-                    STDOUT += r#"+ a1 a2; // adds the two numeric values
+        let io = MemoryIo::new();
+        let mut interpreter = create_builtin_interpreter();
+        interpreter.set_io(io.clone());
+        interpreter.register_builtin("helppredef".to_owned(), 0, |interpreter, _| {
+            interpreter.println(
+                r#"+ a1 a2; // adds the two numeric values
 - a1 a2; // subtracts the two values
 not a1; // returns 1 when a1 is 0 and 0 otherwise
 print a1; // prints the argument to stdout
 let a1 a0; // defines a new variable (eg. let { a; } 4;) more info: github.com/dotjulia/stasis
-if expr body; // executes the expression and then executes the body if it is not 0
-"#;
-                    Ok(interpreter::Value::Number(0))
-                });
+if expr body; // executes the expression and then executes the body if it is not 0"#,
+            )?;
+            Ok(interpreter::Value::Number(0))
+        });
+        Self {
+            engine: Engine::with_interpreter(interpreter),
+            io,
         }
     }
-    let test = unsafe { INTERPRETER.as_mut().unwrap() };
-    return test;
+}
+
+#[cfg(feature = "wasm")]
+thread_local! {
+    static PLAYGROUND: std::cell::RefCell<Playground> = std::cell::RefCell::new(Playground::new());
 }
 
 #[cfg(feature = "wasm")]
 #[wasm_bindgen]
 pub fn test(input: String) -> String {
-    use tokenizer::Tokenizer;
+    use crate::{ast_parser::ProgramAST, engine::Engine};
 
-    use crate::ast_parser::{ExpressionAST, ProgramAST};
-    unsafe { STDOUT = String::new() };
+    PLAYGROUND.with(|playground| {
+        let playground = &mut *playground.borrow_mut();
+        playground.io.take_stdout();
+        let engine = &mut playground.engine;
+        let input = input.trim();
 
-    let interpreter = get_interpreter();
-    let mut tokenizer = Tokenizer::new(&input);
-    tokenizer.verify_syntax();
-
-    let mut should_define_func = None;
-    if let Some(tokenizer::Token::Token(token)) = tokenizer.next() {
-        if token == ":" {
-            should_define_func = if let tokenizer::Token::Token(name) = tokenizer.next().unwrap() {
-                Some(name)
-            } else {
-                panic!()
-            };
-        }
-    } else {
-        tokenizer.back();
-    }
-
-    let ast = match ExpressionAST::parse(tokenizer) {
-        Ok(o) => o,
-        Err(e) => return format!("{:?}", e),
-    };
-    let mut ast = match ProgramAST::parse(ast) {
-        Ok(o) => o,
-        Err(e) => return format!("{:?}", e),
-    };
-    ast.finalize();
-
-    let first_output = if let Some(name) = should_define_func {
-        match ast {
-            ProgramAST::FunctionDef(func) => {
-                interpreter.register_func(name, func);
-                "Ok".to_owned()
+        let first_output = if let Some(definition) = input.strip_prefix(':') {
+            let definition = definition.trim_start();
+            let (name, body) = definition.split_at(
+                definition
+                    .find(char::is_whitespace)
+                    .unwrap_or(definition.len()),
+            );
+            match Engine::parse(body) {
+                Ok(ProgramAST::FunctionDef(func)) => {
+                    engine
+                        .interpreter_mut()
+                        .register_func(name.to_owned(), func);
+                    "Ok".to_owned()
+                }
+                Ok(_) => "Can only define functions".to_owned(),
+                Err(e) => return format!("{:?}", e),
             }
-            _ => "Can only define functions".to_owned(),
-        }
-    } else {
-        format!(
-            "Return Value: {:?}",
-            interpreter.run_anonym_func(ast, vec![])
-        )
-    };
-    format!("{}\n{:?}", unsafe { STDOUT.clone() }, first_output,)
+        } else {
+            match engine.eval(input) {
+                Err(engine::Error::Parsing(e)) => return format!("{:?}", e),
+                result => format!("Return Value: {:?}", result),
+            }
+        };
+        format!("{}\n{:?}", playground.io.take_stdout(), first_output)
+    })
 }
@@ -0,0 +1,687 @@
+use std::{
+    collections::{HashMap, LinkedList},
+    rc::Rc,
+};
+
+use crate::{
+    ast_parser::ProgramAST,
+    interpreter::{
+        BuiltinFunction, FunctionContext, InterpreterContext, InterpreterFunctionDef, RuntimeError,
+        Value, ValueFunction,
+    },
+};
+
+/// Requests builtins make to the evaluator by returning `RuntimeError::Signal`.
+/// `Return`, `Break` and `Continue` also pass through builtins calling back
+/// into stayc, until an evaluation handles them.
+#[derive(Debug)]
+pub enum Signal {
+    /// Calls `func` in place of the builtin, passing its result to `then` if given.
+    /// Unlike calling it from Rust, the callee can yield.
+    Call {
+        func: ValueFunction,
+        args: Vec<Value>,
+        then: Option<ValueFunction>,
+    },
+    /// Suspends the innermost running coroutine, `resume` returns the value
+    Yield(Value),
+    /// Continues the coroutine with the id, `yield` returns the value
+    Resume(usize, Value),
+    /// Calls the function with the current continuation
+    CallCC(ValueFunction),
+    /// Continues a captured continuation, `callcc` returns the value
+    Jump(Rc<Continuation>, Value),
+    /// Calls `body` as long as `cond` returns non-zero
+    While {
+        cond: ValueFunction,
+        body: ValueFunction,
+    },
+    /// Calls `body` with every number from `from` up to (excluding) `to`
+    For {
+        from: usize,
+        to: usize,
+        body: ValueFunction,
+    },
+    /// Leaves the innermost function with parameters, returning the value
+    Return(Value),
+    /// Leaves the innermost loop
+    Break,
+    /// Skips to the next iteration of the innermost loop
+    Continue,
+}
+
+impl From<Signal> for RuntimeError {
+    fn from(signal: Signal) -> Self {
+        RuntimeError::Signal(Box::new(signal))
+    }
+}
+
+/// The rest of an evaluation, captured by `callcc`. It is delimited by the
+/// `execute` it was captured in: once that has returned, calling the
+/// continuation runs the captured frames in place of the caller's evaluation.
+#[derive(Debug)]
+pub struct Continuation {
+    execution: usize,
+    frames: Vec<Frame>,
+    contexts: LinkedList<FunctionContext>,
+}
+
+impl Continuation {
+    /// The values the continuation refers to, so they can be kept visible to
+    /// the garbage collector
+    fn values(&self) -> Vec<(String, Value)> {
+        let mut values: Vec<_> = self
+            .contexts
+            .iter()
+            .flat_map(|context| context.0.iter().cloned())
+            .collect();
+        for frame in &self.frames {
+            frame.visit_numbers(&mut |n| values.push((String::new(), Value::Number(n))));
+        }
+        values
+    }
+}
+
+/// Ids of the `execute` calls on the Rust stack, innermost last
+#[derive(Default)]
+struct Executions {
+    next_id: usize,
+    active: Vec<usize>,
+    /// Frames of the executions waiting for a builtin to return, so the
+    /// garbage collector can see them
+    waiting: Vec<Vec<Frame>>,
+    /// Values rooted by the running builtins, see `InterpreterContext::root`
+    roots: Vec<Value>,
+}
+
+/// What the evaluator has to do with the topmost frame
+#[derive(Clone, Debug)]
+pub(crate) enum Frame {
+    /// Evaluating the callee of a call, its argument is evaluated next
+    Callee(ProgramAST),
+    /// Evaluating the argument the function is applied to
+    Argument(ValueFunction),
+    /// Running the remaining statements of a function body. `return` leaves
+    /// the innermost block that `returns`, ie. of a function with parameters
+    /// that isn't the body of a loop.
+    Block {
+        statements: std::vec::IntoIter<ProgramAST>,
+        pop_context: bool,
+        returns: bool,
+    },
+    /// Running the condition of a `while` loop if `testing`, its body otherwise
+    While {
+        cond: ValueFunction,
+        body: ValueFunction,
+        testing: bool,
+    },
+    /// Running the body of a `for` loop, which continues with `next`
+    For {
+        next: usize,
+        to: usize,
+        body: ValueFunction,
+    },
+    /// Receives what a resumed coroutine yields or returns. `depth` is the
+    /// number of scopes outside the coroutine.
+    Resume { coroutine: usize, depth: usize },
+}
+
+impl Frame {
+    fn visit_numbers(&self, visit: &mut impl FnMut(usize)) {
+        match self {
+            Frame::Argument(func) => Value::Function(func.clone()).visit_numbers(visit),
+            Frame::While { cond, body, .. } => {
+                Value::Function(cond.clone()).visit_numbers(visit);
+                Value::Function(body.clone()).visit_numbers(visit);
+            }
+            Frame::For { body, .. } => Value::Function(body.clone()).visit_numbers(visit),
+            _ => {}
+        }
+    }
+}
+
+enum Step {
+    Eval(ProgramAST),
+    Call(ValueFunction, Vec<Value>),
+    /// Calls the body of a loop, which `return` leaves through
+    Body(ValueFunction, Vec<Value>),
+    Return(Value),
+}
+
+enum Coroutine {
+    /// Not resumed yet, the first `resume` calls the function with its value
+    Start(ValueFunction),
+    Suspended {
+        frames: Vec<Frame>,
+        contexts: LinkedList<FunctionContext>,
+    },
+    Running,
+}
+
+/// Coroutines that haven't finished yet, by id
+#[derive(Default)]
+pub(crate) struct Coroutines {
+    next_id: usize,
+    coroutines: HashMap<usize, Coroutine>,
+}
+
+impl Coroutines {
+    pub(crate) fn spawn(&mut self, start: ValueFunction) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.coroutines.insert(id, Coroutine::Start(start));
+        id
+    }
+
+    pub(crate) fn is_finished(&self, id: usize) -> bool {
+        !self.coroutines.contains_key(&id)
+    }
+
+    /// Visits the numbers in the scopes and frames of suspended coroutines
+    pub(crate) fn visit_numbers(&self, visit: &mut impl FnMut(usize)) {
+        for coroutine in self.coroutines.values() {
+            match coroutine {
+                Coroutine::Start(func) => Value::Function(func.clone()).visit_numbers(visit),
+                Coroutine::Suspended { frames, contexts } => {
+                    for frame in frames {
+                        frame.visit_numbers(visit);
+                    }
+                    for context in contexts {
+                        for (_, value) in &context.0 {
+                            value.visit_numbers(visit);
+                        }
+                    }
+                }
+                Coroutine::Running => {}
+            }
+        }
+    }
+}
+
+impl InterpreterContext {
+    /// Keeps `value` visible to the garbage collector until the builtin
+    /// calling this returns. Builtins holding on to values across calls back
+    /// into stayc have to root them, their arguments are rooted already.
+    pub fn root(&mut self, value: Value) {
+        self.state_or_default::<Executions>().roots.push(value);
+    }
+
+    /// Visits the numbers in the frames of the running executions and in the
+    /// values rooted by builtins
+    pub(crate) fn visit_running(&self, visit: &mut impl FnMut(usize)) {
+        if let Some(executions) = self.state::<Executions>() {
+            for frame in executions.waiting.iter().flatten() {
+                frame.visit_numbers(visit);
+            }
+            for value in &executions.roots {
+                value.visit_numbers(visit);
+            }
+        }
+    }
+
+    /// Calls a builtin with the frames of the execution set aside where the
+    /// garbage collector can see them
+    fn call_builtin(
+        &mut self,
+        frames: &mut Vec<Frame>,
+        builtin: BuiltinFunction,
+        args: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let executions = self.state_or_default::<Executions>();
+        let rooted = executions.roots.len();
+        executions.roots.extend(args.iter().cloned());
+        executions.waiting.push(std::mem::take(frames));
+        let result = builtin(self, args);
+        let executions = self.state_or_default::<Executions>();
+        *frames = executions.waiting.pop().unwrap_or_default();
+        executions.roots.truncate(rooted);
+        result
+    }
+
+    pub(crate) fn eval_program(&mut self, program: ProgramAST) -> Result<Value, RuntimeError> {
+        let base = self.function_context.len();
+        self.execute(base, vec![], Step::Eval(program))
+    }
+
+    pub(crate) fn eval_call(
+        &mut self,
+        func: ValueFunction,
+        args: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let base = self.function_context.len();
+        self.execute(base, vec![], Step::Call(func, args))
+    }
+
+    /// Calls the body of a loop run by a builtin, letting `return` through to
+    /// the function around the loop
+    pub(crate) fn eval_body(
+        &mut self,
+        func: ValueFunction,
+        args: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let base = self.function_context.len();
+        self.execute(base, vec![], Step::Body(func, args))
+    }
+
+    /// Runs the statements of a function body in a new scope, which is kept
+    /// afterwards unless `pop_context` is set
+    pub(crate) fn eval_block(
+        &mut self,
+        block: Vec<ProgramAST>,
+        args: Vec<(String, Value)>,
+        pop_context: bool,
+    ) -> Result<Value, RuntimeError> {
+        let base = self.function_context.len();
+        let mut frames = vec![];
+        let step = self.enter_block(&mut frames, block, args, pop_context, true)?;
+        self.execute(base, frames, step)
+    }
+
+    fn enter_block(
+        &mut self,
+        frames: &mut Vec<Frame>,
+        block: Vec<ProgramAST>,
+        args: Vec<(String, Value)>,
+        pop_context: bool,
+        returns: bool,
+    ) -> Result<Step, RuntimeError> {
+        self.function_context.push_back(FunctionContext(args));
+        let mut statements = block.into_iter();
+        let first = statements.next().ok_or(RuntimeError::EmptyFunction)?;
+        frames.push(Frame::Block {
+            statements,
+            pop_context,
+            returns,
+        });
+        Ok(Step::Eval(first))
+    }
+
+    /// Evaluates on an explicit stack of frames instead of the Rust stack, so
+    /// a coroutine's frames can be set aside while it is suspended. Builtins
+    /// calling back into stayc start a nested `execute`, which coroutines
+    /// can't yield across. `base` is the number of scopes outside of it.
+    fn execute(
+        &mut self,
+        base: usize,
+        frames: Vec<Frame>,
+        step: Step,
+    ) -> Result<Value, RuntimeError> {
+        let executions = self.state_or_default::<Executions>();
+        let id = executions.next_id;
+        executions.next_id += 1;
+        executions.active.push(id);
+        let result = self.execute_frames(id, base, frames, step);
+        let executions = self.state_or_default::<Executions>();
+        executions.active.pop();
+        if !executions.active.is_empty() {
+            return result;
+        }
+        // signals nothing handled don't leave the interpreter
+        result.map_err(|error| match error {
+            RuntimeError::Signal(signal) => match *signal {
+                Signal::Return(_) => {
+                    RuntimeError::ExplicitlyRaisedMessage("return outside of a function")
+                }
+                Signal::Break | Signal::Continue => {
+                    RuntimeError::ExplicitlyRaisedMessage("break or continue outside of a loop")
+                }
+                Signal::Jump(..) => RuntimeError::ExplicitlyRaisedMessage(
+                    "continuation called outside of its evaluation",
+                ),
+                signal => RuntimeError::Signal(Box::new(signal)),
+            },
+            error => error,
+        })
+    }
+
+    fn execute_frames(
+        &mut self,
+        execution: usize,
+        base: usize,
+        mut frames: Vec<Frame>,
+        mut step: Step,
+    ) -> Result<Value, RuntimeError> {
+        loop {
+            let value = match step {
+                Step::Eval(program) => match program {
+                    ProgramAST::FunctionCall { function, arg } => {
+                        frames.push(Frame::Callee(*arg));
+                        step = Step::Eval(*function);
+                        continue;
+                    }
+                    ProgramAST::FunctionDef(func_def) => Value::Function(ValueFunction {
+                        bound_context: vec![],
+                        func: InterpreterFunctionDef::FunctionDef {
+                            name: "anonymous".to_owned(),
+                            def: func_def,
+                        },
+                        bound_variables: vec![],
+                    }),
+                    ProgramAST::FunctionRef { token } => self
+                        .resolve(&token)
+                        .ok_or(RuntimeError::UndefinedFunctionReference(token))?,
+                    ProgramAST::Value { value } => Value::Number(value),
+                },
+                Step::Call(func, args) => {
+                    step = self.call(execution, base, &mut frames, func, args, false)?;
+                    continue;
+                }
+                Step::Body(func, args) => {
+                    step = self.call(execution, base, &mut frames, func, args, true)?;
+                    continue;
+                }
+                Step::Return(value) => value,
+            };
+
+            step = match frames.pop() {
+                None => return Ok(value),
+                Some(Frame::Callee(arg)) => match value {
+                    Value::Number(n) => return Err(RuntimeError::ValueNotAFunction(n)),
+                    Value::Record(record) => return Err(RuntimeError::RecordNotAFunction(record)),
+                    Value::Function(func) => {
+                        frames.push(Frame::Argument(func));
+                        Step::Eval(arg)
+                    }
+                },
+                Some(Frame::Argument(mut func)) => {
+                    func.bound_variables.push(value);
+                    let arg_count = match &func.func {
+                        InterpreterFunctionDef::BuiltIn {
+                            name: _,
+                            arg_count,
+                            func: _,
+                        } => *arg_count,
+                        InterpreterFunctionDef::FunctionDef { name: _, def } => {
+                            def.arg_tokens.len()
+                        }
+                    };
+                    if func.bound_variables.len() >= arg_count {
+                        // don't pass arguments since values are already bound
+                        Step::Call(func, vec![])
+                    } else {
+                        Step::Return(Value::Function(func))
+                    }
+                }
+                Some(Frame::Block {
+                    mut statements,
+                    pop_context,
+                    returns,
+                }) => match statements.next() {
+                    Some(statement) => {
+                        frames.push(Frame::Block {
+                            statements,
+                            pop_context,
+                            returns,
+                        });
+                        Step::Eval(statement)
+                    }
+                    None => {
+                        if pop_context {
+                            self.function_context.pop_back();
+                        }
+                        Step::Return(value)
+                    }
+                },
+                Some(Frame::While {
+                    cond,
+                    body,
+                    testing: true,
+                }) => match value {
+                    Value::Number(n) if n != 0 => {
+                        frames.push(Frame::While {
+                            cond,
+                            body: body.clone(),
+                            testing: false,
+                        });
+                        Step::Body(body, vec![Value::Number(0)])
+                    }
+                    _ => Step::Return(Value::Number(0)),
+                },
+                Some(Frame::While {
+                    cond,
+                    body,
+                    testing: false,
+                }) => {
+                    frames.push(Frame::While {
+                        cond: cond.clone(),
+                        body,
+                        testing: true,
+                    });
+                    Step::Call(cond, vec![Value::Number(0)])
+                }
+                Some(Frame::For { next, to, body }) => for_iteration(&mut frames, next, to, body),
+                Some(Frame::Resume { coroutine, depth }) => {
+                    self.state_or_default::<Coroutines>()
+                        .coroutines
+                        .remove(&coroutine);
+                    while self.function_context.len() > depth {
+                        self.function_context.pop_back();
+                    }
+                    Step::Return(value)
+                }
+            };
+        }
+    }
+
+    /// Calls `func` with `args`. The block of a loop `body` doesn't stop
+    /// `return`, which leaves the function around the loop instead.
+    fn call(
+        &mut self,
+        execution: usize,
+        base: usize,
+        frames: &mut Vec<Frame>,
+        mut func: ValueFunction,
+        mut args: Vec<Value>,
+        body: bool,
+    ) -> Result<Step, RuntimeError> {
+        func.bound_variables.append(&mut args);
+        match func.func {
+            InterpreterFunctionDef::BuiltIn {
+                name: _,
+                arg_count: _,
+                func: builtin_func,
+            } => match self.call_builtin(frames, builtin_func, func.bound_variables) {
+                Ok(value) => Ok(Step::Return(value)),
+                Err(RuntimeError::Signal(signal)) => self.handle(execution, base, frames, *signal),
+                Err(error) => Err(error),
+            },
+            InterpreterFunctionDef::FunctionDef { name: _, def } => {
+                let mut vars: Vec<_> = func
+                    .bound_variables
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, e)| (def.arg_tokens.get(i).cloned().unwrap_or_default(), e))
+                    .collect();
+                vars.append(&mut func.bound_context);
+                let returns = !body && !def.arg_tokens.is_empty();
+                self.enter_block(frames, def.block, vars, true, returns)
+            }
+        }
+    }
+
+    fn handle(
+        &mut self,
+        execution: usize,
+        base: usize,
+        frames: &mut Vec<Frame>,
+        signal: Signal,
+    ) -> Result<Step, RuntimeError> {
+        match signal {
+            Signal::Call { func, args, then } => {
+                if let Some(then) = then {
+                    frames.push(Frame::Argument(then));
+                }
+                Ok(Step::Call(func, args))
+            }
+            Signal::Yield(value) => {
+                let index = frames
+                    .iter()
+                    .rposition(|frame| matches!(frame, Frame::Resume { .. }))
+                    .ok_or(RuntimeError::ExplicitlyRaisedMessage(
+                        "yield outside of a coroutine, or from a callback of a builtin",
+                    ))?;
+                let suspended = frames.split_off(index + 1);
+                let Some(Frame::Resume { coroutine, depth }) = frames.pop() else {
+                    unreachable!()
+                };
+                let contexts = self.function_context.split_off(depth);
+                self.state_or_default::<Coroutines>().coroutines.insert(
+                    coroutine,
+                    Coroutine::Suspended {
+                        frames: suspended,
+                        contexts,
+                    },
+                );
+                Ok(Step::Return(value))
+            }
+            Signal::Resume(coroutine, value) => {
+                let coroutines = &mut self.state_or_default::<Coroutines>().coroutines;
+                let state = match coroutines.get_mut(&coroutine) {
+                    Some(state) => std::mem::replace(state, Coroutine::Running),
+                    None => {
+                        return Err(RuntimeError::ExplicitlyRaisedMessage(
+                            "the coroutine has finished",
+                        ))
+                    }
+                };
+                frames.push(Frame::Resume {
+                    coroutine,
+                    depth: self.function_context.len(),
+                });
+                match state {
+                    Coroutine::Start(func) => Ok(Step::Call(func, vec![value])),
+                    Coroutine::Suspended {
+                        frames: mut suspended,
+                        mut contexts,
+                    } => {
+                        frames.append(&mut suspended);
+                        self.function_context.append(&mut contexts);
+                        Ok(Step::Return(value))
+                    }
+                    Coroutine::Running => Err(RuntimeError::ExplicitlyRaisedMessage(
+                        "the coroutine is already running",
+                    )),
+                }
+            }
+            Signal::CallCC(func) => {
+                let mut contexts = self.function_context.split_off(base);
+                let continuation = Rc::new(Continuation {
+                    execution,
+                    frames: frames.clone(),
+                    contexts: contexts.clone(),
+                });
+                self.function_context.append(&mut contexts);
+                let jump = continuation.clone();
+                let continuation = ValueFunction {
+                    func: InterpreterFunctionDef::BuiltIn {
+                        name: "continuation".to_owned(),
+                        arg_count: 1,
+                        func: Rc::new(move |_, mut args| {
+                            Err(Signal::Jump(jump.clone(), args.remove(0)).into())
+                        }),
+                    },
+                    // unused by calls, but seen by the garbage collector
+                    bound_context: continuation.values(),
+                    bound_variables: vec![],
+                };
+                Ok(Step::Call(func, vec![Value::Function(continuation)]))
+            }
+            Signal::While { cond, body } => {
+                frames.push(Frame::While {
+                    cond: cond.clone(),
+                    body,
+                    testing: true,
+                });
+                Ok(Step::Call(cond, vec![Value::Number(0)]))
+            }
+            Signal::For { from, to, body } => Ok(for_iteration(frames, from, to, body)),
+            Signal::Return(value) => {
+                if !self.unwind(frames, |frame| {
+                    matches!(frame, Frame::Block { returns: true, .. })
+                }) {
+                    return self.propagate(base, Signal::Return(value));
+                }
+                if let Some(Frame::Block {
+                    pop_context: true, ..
+                }) = frames.pop()
+                {
+                    self.function_context.pop_back();
+                }
+                Ok(Step::Return(value))
+            }
+            Signal::Break | Signal::Continue => {
+                if !self.unwind(frames, |frame| {
+                    matches!(frame, Frame::While { .. } | Frame::For { .. })
+                }) {
+                    return self.propagate(base, signal);
+                }
+                match (signal, frames.pop()) {
+                    (Signal::Continue, Some(Frame::While { cond, body, .. })) => {
+                        frames.push(Frame::While {
+                            cond: cond.clone(),
+                            body,
+                            testing: true,
+                        });
+                        Ok(Step::Call(cond, vec![Value::Number(0)]))
+                    }
+                    (Signal::Continue, Some(Frame::For { next, to, body })) => {
+                        Ok(for_iteration(frames, next, to, body))
+                    }
+                    _ => Ok(Step::Return(Value::Number(0))),
+                }
+            }
+            Signal::Jump(continuation, value) => {
+                let active = &self.state_or_default::<Executions>().active;
+                if continuation.execution != execution && active.contains(&continuation.execution) {
+                    // unwind the builtins in between to the execution it was captured in
+                    return self.propagate(base, Signal::Jump(continuation, value));
+                }
+                while self.function_context.len() > base {
+                    self.function_context.pop_back();
+                }
+                self.function_context
+                    .append(&mut continuation.contexts.clone());
+                *frames = continuation.frames.clone();
+                Ok(Step::Return(value))
+            }
+        }
+    }
+
+    /// Pops the frames above the innermost one matching `target`, dropping
+    /// their scopes. Returns false, leaving the frames alone, if there is none.
+    fn unwind(&mut self, frames: &mut Vec<Frame>, target: impl Fn(&Frame) -> bool) -> bool {
+        let Some(index) = frames.iter().rposition(target) else {
+            return false;
+        };
+        for frame in frames.split_off(index + 1) {
+            if let Frame::Block {
+                pop_context: true, ..
+            } = frame
+            {
+                self.function_context.pop_back();
+            }
+        }
+        true
+    }
+
+    /// Leaves this evaluation, passing the signal on to the builtin that started it
+    fn propagate(&mut self, base: usize, signal: Signal) -> Result<Step, RuntimeError> {
+        while self.function_context.len() > base {
+            self.function_context.pop_back();
+        }
+        Err(signal.into())
+    }
+}
+
+/// Calls the body of a `for` loop with `i`, or ends the loop once it reaches `to`
+fn for_iteration(frames: &mut Vec<Frame>, i: usize, to: usize, body: ValueFunction) -> Step {
+    if i >= to {
+        return Step::Return(Value::Number(0));
+    }
+    frames.push(Frame::For {
+        next: i + 1,
+        to,
+        body: body.clone(),
+    });
+    Step::Body(body, vec![Value::Number(i)])
+}
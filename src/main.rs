@@ -1,33 +1,18 @@
-use std::{
-    env, fs,
-    io::{BufRead, Write},
-    time::Instant,
-};
+use std::{io::Write, time::Instant};
 
-use ast_parser::{ExpressionAST, ParsingError, ProgramAST};
 use clap::Parser;
-use interpreter::InterpreterContext;
-use tokenizer::Tokenizer;
-
-use crate::{builtin::create_builtin_interpreter, tokenizer::Token};
-
-mod ast_parser;
-mod builtin;
-mod interpreter;
-mod str_ext;
-mod tokenizer;
+use stayc::{
+    ast_parser::ProgramAST,
+    builtin::{create_builtin_interpreter_without_prelude, set_strict_memory},
+    engine::{Engine, Error},
+};
 
-fn run_file(interpreter: &mut InterpreterContext, file: String) -> Result<(), ParsingError> {
-    let file = fs::read_to_string(file).unwrap();
-    let mut tokenizer = Tokenizer::new(&file);
-    tokenizer.verify_syntax();
-    let ast = ExpressionAST::parse(tokenizer).unwrap();
-    let mut ast = ProgramAST::parse(ast)?;
-    ast.finalize();
+fn run_file(engine: &mut Engine, file: String) -> Result<(), Error> {
     let before = Instant::now();
-    match interpreter.run_anonym_func(ast, vec![], false) {
+    match engine.eval_file(file) {
         Ok(val) => println!("Program returned: {:?} in {:?}", val, before.elapsed()),
-        Err(err) => println!("{:?}", err),
+        Err(Error::Runtime(err)) => println!("{:?}", err),
+        Err(err) => return Err(err),
     }
     Ok(())
 }
@@ -39,76 +24,82 @@ struct Arguments {
     run: Option<String>,
     #[arg(short, long)]
     preload: Option<String>,
+    /// Bounds-check every pointer against the allocation it was derived from
+    #[arg(long)]
+    strict: bool,
+    /// Start without the standard library
+    #[arg(long)]
+    no_prelude: bool,
+    /// Allow running other programs, eg. with `exec`
+    #[arg(long)]
+    allow_exec: bool,
+    /// Allow reading files below this path (can be given multiple times)
+    #[arg(long, value_name = "PATH")]
+    allow_read: Vec<String>,
+    /// Allow writing files below this path (can be given multiple times)
+    #[arg(long, value_name = "PATH")]
+    allow_write: Vec<String>,
 }
 
-fn main() -> Result<(), ParsingError> {
-    let mut interpreter = create_builtin_interpreter();
+fn main() -> Result<(), Error> {
     let args = Arguments::parse();
+    let mut engine = if args.no_prelude {
+        Engine::with_interpreter(create_builtin_interpreter_without_prelude())
+    } else {
+        Engine::new()
+    };
+    set_strict_memory(engine.interpreter_mut(), args.strict);
+    let permissions = engine.interpreter_mut().permissions_mut();
+    permissions.stdin = true;
+    permissions.process = args.allow_exec;
+    for path in &args.allow_read {
+        permissions.allow_read(path);
+    }
+    for path in &args.allow_write {
+        permissions.allow_write(path);
+    }
 
-    if args.run.is_some() {
-        return run_file(&mut interpreter, args.run.unwrap());
+    if let Some(file) = args.run {
+        return run_file(&mut engine, file);
     }
 
-    if args.preload.is_some() {
-        println!("{:?}", run_file(&mut interpreter, args.preload.unwrap()));
+    if let Some(file) = args.preload {
+        println!("{:?}", run_file(&mut engine, file));
     }
 
     let stdin = std::io::stdin();
     print!("> ");
     std::io::stdout().flush().unwrap();
-    for line in stdin.lock().lines() {
-        let mut line = line.unwrap().trim().to_owned();
-        if !line.starts_with("{") {
-            line = "{".to_owned() + &line + ";}";
-        }
-        let mut tokenizer = Tokenizer::new(&line);
-        tokenizer.verify_syntax();
-        let mut should_define_func = None;
-        if let Some(Token::Token(token)) = tokenizer.next() {
-            if token == ":" {
-                should_define_func = if let Token::Token(name) = tokenizer.next().unwrap() {
-                    Some(name)
-                } else {
-                    panic!()
-                };
-            }
-        } else {
-            tokenizer.back();
-        }
-        let ast = match ExpressionAST::parse(tokenizer) {
-            Ok(o) => o,
-            Err(e) => {
-                println!("{:?}", e);
-                print!("> ");
-                std::io::stdout().flush().unwrap();
-                continue;
-            }
-        };
-        let mut ast = match ProgramAST::parse(ast) {
-            Ok(o) => o,
-            Err(e) => {
-                println!("{:?}", e);
-                print!("> ");
-                std::io::stdout().flush().unwrap();
-                continue;
-            }
-        };
-        ast.finalize();
-
+    // stdin isn't kept locked, so `read` can take lines from it too
+    let mut line = String::new();
+    while stdin.read_line(&mut line).unwrap() > 0 {
+        let line = std::mem::take(&mut line);
+        let line = line.trim();
         let before = Instant::now();
-        if let Some(name) = should_define_func {
-            match ast {
-                ProgramAST::FunctionDef(func) => interpreter.register_func(name, func),
-                _ => {
-                    println!("Can only define functions")
-                }
+        // `: name { ... }` defines a function
+        if let Some(definition) = line.strip_prefix(':') {
+            let definition = definition.trim_start();
+            let (name, body) = definition.split_at(
+                definition
+                    .find(char::is_whitespace)
+                    .unwrap_or(definition.len()),
+            );
+            match Engine::parse(body) {
+                Ok(ProgramAST::FunctionDef(func)) => engine
+                    .interpreter_mut()
+                    .register_func(name.to_owned(), func),
+                Ok(_) => println!("Can only define functions"),
+                Err(e) => println!("{:?}", e),
             }
         } else {
-            println!(
-                "Return Value: {:?}, evaluated in {:?}",
-                interpreter.run_anonym_func(ast, vec![], false),
-                before.elapsed()
-            );
+            match engine.eval(line) {
+                Err(error @ Error::Parsing(_)) => println!("{}", error),
+                result => println!(
+                    "Return Value: {:?}, evaluated in {:?}",
+                    result,
+                    before.elapsed()
+                ),
+            }
         }
         print!("> ");
         std::io::stdout().flush().unwrap();
@@ -0,0 +1,254 @@
+use std::rc::Rc;
+
+use crate::interpreter::{
+    InterpreterContext, InterpreterFunctionDef, Record, RuntimeError, Value, ValueFunction,
+};
+
+/// Conversion of builtin arguments from interpreter values
+pub trait FromValue: Sized {
+    /// Name of the expected type used in error messages
+    const TYPE_NAME: &'static str;
+    /// Returns the value back if it has the wrong type
+    fn from_value(value: Value) -> Result<Self, Value>;
+}
+
+/// Conversion of builtin return values into interpreter values
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+impl FromValue for Value {
+    const TYPE_NAME: &'static str = "value";
+    fn from_value(value: Value) -> Result<Self, Value> {
+        Ok(value)
+    }
+}
+
+impl FromValue for usize {
+    const TYPE_NAME: &'static str = "number";
+    fn from_value(value: Value) -> Result<Self, Value> {
+        match value {
+            Value::Number(n) => Ok(n),
+            value => Err(value),
+        }
+    }
+}
+
+impl FromValue for bool {
+    const TYPE_NAME: &'static str = "number";
+    fn from_value(value: Value) -> Result<Self, Value> {
+        usize::from_value(value).map(|n| n != 0)
+    }
+}
+
+impl FromValue for ValueFunction {
+    const TYPE_NAME: &'static str = "function";
+    fn from_value(value: Value) -> Result<Self, Value> {
+        match value {
+            Value::Function(func) => Ok(func),
+            value => Err(value),
+        }
+    }
+}
+
+impl FromValue for Record {
+    const TYPE_NAME: &'static str = "record";
+    fn from_value(value: Value) -> Result<Self, Value> {
+        match value {
+            Value::Record(record) => Ok(record),
+            value => Err(value),
+        }
+    }
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+impl IntoValue for usize {
+    fn into_value(self) -> Value {
+        Value::Number(self)
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        Value::Number(self as usize)
+    }
+}
+
+impl IntoValue for ValueFunction {
+    fn into_value(self) -> Value {
+        Value::Function(self)
+    }
+}
+
+/// A Rust function usable as a builtin. The arity and argument conversions are
+/// derived from its signature, eg. `fn(&mut BuiltinState, usize, usize) -> Result<usize, RuntimeError>`.
+/// `State` is the value the function receives as its first parameter.
+/// Arguments past the signature are ignored. Calls always pass at least one
+/// argument, so functions without parameters are called like `read 0`.
+pub trait NativeFunction<State, Args> {
+    const ARG_COUNT: usize;
+    fn call(&self, state: &mut State, name: &str, args: Vec<Value>) -> Result<Value, RuntimeError>;
+}
+
+macro_rules! impl_native_function {
+    ($($arg:ident),*) => {
+        impl<State, Func, Ret, $($arg,)*> NativeFunction<State, ($($arg,)*)> for Func
+        where
+            Func: Fn(&mut State, $($arg),*) -> Result<Ret, RuntimeError>,
+            Ret: IntoValue,
+            $($arg: FromValue,)*
+        {
+            const ARG_COUNT: usize = <[&str]>::len(&[$(stringify!($arg)),*]);
+
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn call(
+                &self,
+                state: &mut State,
+                name: &str,
+                args: Vec<Value>,
+            ) -> Result<Value, RuntimeError> {
+                let mut args = args.into_iter().enumerate();
+                $(
+                    let $arg = match args.next() {
+                        Some((index, value)) => match $arg::from_value(value) {
+                            Ok(arg) => arg,
+                            Err(value) => {
+                                return Err(RuntimeError::WrongArgumentType {
+                                    builtin: name.to_owned(),
+                                    index,
+                                    expected: $arg::TYPE_NAME,
+                                    found: Box::new(value),
+                                })
+                            }
+                        },
+                        None => return Err(RuntimeError::ExplicitlyRaisedMessage("missing argument")),
+                    };
+                )*
+                self(state, $($arg),*).map(IntoValue::into_value)
+            }
+        }
+    };
+}
+
+impl_native_function!();
+impl_native_function!(A1);
+impl_native_function!(A1, A2);
+impl_native_function!(A1, A2, A3);
+impl_native_function!(A1, A2, A3, A4);
+
+impl IntoValue for Record {
+    fn into_value(self) -> Value {
+        Value::Record(self)
+    }
+}
+
+impl InterpreterContext {
+    /// Registers a Rust function operating on the interpreter state `S`, which
+    /// is created on first use
+    pub fn register_native<S: Default + 'static, Args, F>(&mut self, name: &str, func: F)
+    where
+        F: NativeFunction<S, Args> + 'static,
+    {
+        let builtin_name = name.to_owned();
+        self.register_builtin(name.to_owned(), F::ARG_COUNT, move |interpreter, args| {
+            func.call(interpreter.state_or_default::<S>(), &builtin_name, args)
+        });
+    }
+
+    /// Registers a Rust function receiving the interpreter itself, for builtins
+    /// that need to call back into stayc code
+    pub fn register_native_fn<Args, F>(&mut self, name: &str, func: F)
+    where
+        F: NativeFunction<InterpreterContext, Args> + 'static,
+    {
+        let builtin_name = name.to_owned();
+        self.register_builtin(name.to_owned(), F::ARG_COUNT, move |interpreter, args| {
+            func.call(interpreter, &builtin_name, args)
+        });
+    }
+}
+
+/// Creates a function value calling `func` with its first parameters bound to
+/// `bound_variables`. Builtins use this to return values carrying state, which
+/// stays visible to the garbage collector.
+pub fn partial<Args, F>(name: &str, func: F, bound_variables: Vec<Value>) -> ValueFunction
+where
+    F: NativeFunction<InterpreterContext, Args> + 'static,
+{
+    let builtin_name = name.to_owned();
+    ValueFunction {
+        func: InterpreterFunctionDef::BuiltIn {
+            name: name.to_owned(),
+            arg_count: F::ARG_COUNT,
+            func: Rc::new(move |interpreter, args| func.call(interpreter, &builtin_name, args)),
+        },
+        bound_context: vec![],
+        bound_variables,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        builtin::BuiltinState,
+        engine::{Engine, Error},
+        interpreter::{InterpreterContext, RuntimeError, Value, ValueFunction},
+    };
+
+    fn apply(
+        interpreter: &mut InterpreterContext,
+        func: ValueFunction,
+        arg: usize,
+    ) -> Result<Value, RuntimeError> {
+        interpreter.apply(func, vec![Value::Number(arg)])
+    }
+
+    fn seven(_: &mut BuiltinState) -> Result<usize, RuntimeError> {
+        Ok(7)
+    }
+
+    #[test]
+    fn signatures_define_arity_and_argument_types() {
+        let mut engine = Engine::new();
+        engine.interpreter_mut().register_native_fn("apply", apply);
+        engine.interpreter_mut().register_native("seven", seven);
+        assert!(matches!(
+            engine.eval("apply { x => + x 1; } 2"),
+            Ok(Value::Number(3))
+        ));
+        // partially applied until both arguments are there
+        assert!(matches!(
+            engine.eval("apply { x => x; }"),
+            Ok(Value::Function(_))
+        ));
+        match engine.eval("apply 1 2") {
+            Err(Error::Runtime(RuntimeError::WrongArgumentType {
+                builtin,
+                index,
+                expected,
+                found,
+            })) => {
+                assert_eq!(
+                    (builtin.as_str(), index, expected),
+                    ("apply", 0, "function")
+                );
+                assert!(matches!(*found, Value::Number(1)));
+            }
+            other => panic!("expected a wrong argument type, got {:?}", other),
+        }
+
+        // arguments past the signature are ignored
+        assert!(matches!(engine.eval("seven 0"), Ok(Value::Number(7))));
+        engine.eval("let { identity; } { x => x; }").unwrap();
+        let identity = engine.get_global("identity").unwrap();
+        assert!(matches!(
+            engine.call("apply", vec![identity, Value::Number(4), Value::Number(5)]),
+            Ok(Value::Number(4))
+        ));
+    }
+}
@@ -0,0 +1,115 @@
+use std::path::{Component, Path, PathBuf};
+
+use crate::interpreter::{InterpreterContext, RuntimeError};
+
+/// Something a builtin needs to be allowed to do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Permission {
+    Process,
+    Read(PathBuf),
+    Write(PathBuf),
+    Env,
+    Stdin,
+    Network,
+}
+
+/// The capabilities granted to the code an interpreter runs. Everything is
+/// denied by default.
+#[derive(Debug, Clone, Default)]
+pub struct Permissions {
+    /// Running other programs, eg. through `exec`
+    pub process: bool,
+    /// Directories and files that may be read, including everything below them
+    pub read: Vec<PathBuf>,
+    /// Directories and files that may be written, including everything below them
+    pub write: Vec<PathBuf>,
+    pub env: bool,
+    pub stdin: bool,
+    pub network: bool,
+}
+
+impl Permissions {
+    pub fn all() -> Self {
+        Self {
+            process: true,
+            read: vec![PathBuf::from("/")],
+            write: vec![PathBuf::from("/")],
+            env: true,
+            stdin: true,
+            network: true,
+        }
+    }
+
+    pub fn allow_read(&mut self, path: impl AsRef<Path>) {
+        self.read.push(absolute(path.as_ref()));
+    }
+
+    pub fn allow_write(&mut self, path: impl AsRef<Path>) {
+        self.write.push(absolute(path.as_ref()));
+    }
+
+    pub fn allows(&self, permission: &Permission) -> bool {
+        match permission {
+            Permission::Process => self.process,
+            Permission::Read(path) => within(&self.read, path),
+            Permission::Write(path) => within(&self.write, path),
+            Permission::Env => self.env,
+            Permission::Stdin => self.stdin,
+            Permission::Network => self.network,
+        }
+    }
+}
+
+/// Resolves `path` as far as it exists, so `..` and symlinks can't be used to
+/// leave an allowed directory
+fn absolute(path: &Path) -> PathBuf {
+    if let Ok(path) = path.canonicalize() {
+        return path;
+    }
+    let mut resolved = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => absolute(parent),
+        _ => std::env::current_dir().unwrap_or_default(),
+    };
+    match path.components().next_back() {
+        Some(Component::ParentDir) => {
+            resolved.pop();
+        }
+        Some(Component::CurDir) | None => {}
+        Some(component) => resolved.push(component),
+    }
+    resolved
+}
+
+fn within(roots: &[PathBuf], path: &Path) -> bool {
+    let path = absolute(path);
+    roots.iter().any(|root| path.starts_with(root))
+}
+
+impl InterpreterContext {
+    /// Fails with `RuntimeError::PermissionDenied` unless `permission` was granted
+    pub fn check_permission(&self, permission: Permission) -> Result<(), RuntimeError> {
+        if self.permissions().allows(&permission) {
+            Ok(())
+        } else {
+            Err(RuntimeError::PermissionDenied(permission))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Permission, Permissions};
+
+    #[test]
+    fn paths_outside_allowed_directories_are_denied() {
+        let dir = std::env::temp_dir().join("stayc-permissions");
+        std::fs::create_dir_all(dir.join("inner")).unwrap();
+        let mut permissions = Permissions::default();
+        permissions.allow_read(dir.join("inner"));
+        assert!(permissions.allows(&Permission::Read(dir.join("inner/file"))));
+        assert!(!permissions.allows(&Permission::Read(dir.join("inner/../file"))));
+        assert!(!permissions.allows(&Permission::Read(dir.join("inner/missing/../../file"))));
+        assert!(!permissions.allows(&Permission::Write(dir.join("inner/file"))));
+        assert!(!permissions.allows(&Permission::Process));
+    }
+}
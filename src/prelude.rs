@@ -0,0 +1,34 @@
+use crate::{
+    ast_parser::ProgramAST,
+    interpreter::{FunctionContext, InterpreterContext, RuntimeError},
+};
+
+/// The standard library loaded into every builtin interpreter
+pub const PRELUDE: &str = include_str!("prelude.st");
+/// Bumped whenever a definition of the prelude changes in an incompatible way
+pub const PRELUDE_VERSION: u32 = 6;
+
+/// Evaluates the prelude, keeping its definitions as globals
+pub fn load_prelude(interpreter: &mut InterpreterContext) -> Result<(), RuntimeError> {
+    let ast = ProgramAST::parse_source(PRELUDE).expect("the prelude parses");
+    if interpreter.function_context.is_empty() {
+        interpreter
+            .function_context
+            .push_back(FunctionContext(vec![]));
+    }
+    let depth = interpreter.function_context.len();
+    let result = interpreter.run_anonym_func(ast, vec![], false);
+    if result.is_ok() && interpreter.function_context.len() > depth {
+        let FunctionContext(mut bindings) = interpreter.function_context.pop_back().unwrap();
+        interpreter
+            .function_context
+            .back_mut()
+            .unwrap()
+            .0
+            .append(&mut bindings);
+    }
+    while interpreter.function_context.len() > depth {
+        interpreter.function_context.pop_back();
+    }
+    result.map(|_| ())
+}
@@ -1,3 +1,5 @@
+use std::fmt::Display;
+
 use crate::str_ext::SplitKeepingDelimiterExt;
 
 pub struct Tokenizer {
@@ -65,23 +67,41 @@ impl Tokenizer {
             self.pos -= 1;
         }
     }
-    pub fn verify_syntax(&mut self) {
+    /// Checks that every statement ends in a ';'
+    pub fn verify_syntax(&mut self) -> Result<(), SyntaxError> {
         if self.pos != 0 {
-            return;
+            return Ok(());
         }
         for (i, token) in self.content.iter().enumerate() {
-            if token == "}" && self.content[i - 1] != ";" {
-                println!("Token before '}}' is not a ';'. All statements must end in a ';'");
-                panic!();
+            if token == "}" && (i == 0 || self.content[i - 1] != ";") {
+                return Err(SyntaxError(i));
             }
         }
         self.pos = 0;
+        Ok(())
     }
     pub fn pos(&self) -> usize {
         self.pos
     }
 }
 
+/// Token before '}' is not a ';'. All statements must end in a ';'
+#[derive(Debug)]
+pub struct SyntaxError(usize);
+
+impl SyntaxError {
+    /// Index of the '}' token missing the ';' in front of it
+    pub fn position(&self) -> usize {
+        self.0
+    }
+}
+
+impl Display for SyntaxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("missing ';' before '}}' at token {}", self.0))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Token {
     Token(String),